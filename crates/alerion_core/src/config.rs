@@ -15,12 +15,107 @@ pub struct AlerionApi {
     pub host: IpAddr,
     pub port: u16,
     pub ssl: AlerionApiSsl,
+    /// Origins allowed to use the API from a browser, on top of the panel.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    /// Whether browsers may reach the API from a public page when the daemon
+    /// is on a private network.
+    #[serde(default)]
+    pub allow_cors_private_network: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AlerionAuthentication {
     pub token: String,
     pub token_id: String,
+    /// Credentials accepted on top of the main token, so node credentials can
+    /// be rotated without downtime.
+    #[serde(default)]
+    pub extra_tokens: Vec<AlerionExtraToken>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AlerionExtraToken {
+    pub token: String,
+    pub token_id: String,
+    /// Unix timestamp, in seconds, after which the token is refused.
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AlerionCrashDetection {
+    /// Whether servers that die unexpectedly are restarted.
+    pub enabled: bool,
+    /// Whether a process exiting with status 0 while running counts as a crash.
+    pub detect_clean_exit_as_crash: bool,
+    /// Seconds after a crash during which another crash is not restarted.
+    pub timeout: u64,
+}
+
+impl Default for AlerionCrashDetection {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            detect_clean_exit_as_crash: true,
+            timeout: 60,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AlerionThrottles {
+    /// Whether console output is rate limited at all.
+    pub enabled: bool,
+    /// Lines a server may print per interval before its output is dropped.
+    pub lines: u64,
+    /// Length of the interval, in milliseconds.
+    pub line_reset_interval: u64,
+    /// Throttle activations after which the server is stopped.
+    pub maximum_trigger_count: u64,
+    /// Milliseconds without an activation after which one is forgiven.
+    pub decay_interval: u64,
+}
+
+impl Default for AlerionThrottles {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            lines: 2000,
+            line_reset_interval: 100,
+            maximum_trigger_count: 5,
+            decay_interval: 10_000,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AlerionDocker {
+    /// Address of the Docker network gateway, which is how containers reach
+    /// the host.
+    pub interface: String,
+}
+
+impl Default for AlerionDocker {
+    fn default() -> Self {
+        Self {
+            interface: "172.18.0.1".to_owned(),
+        }
+    }
+}
+
+/// The user server containers run as, which also owns the files in server
+/// volumes so the game process can write to them.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct AlerionUser {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl Default for AlerionUser {
+    fn default() -> Self {
+        Self { uid: 988, gid: 988 }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -30,6 +125,41 @@ pub struct AlerionConfig {
     pub api: AlerionApi,
     pub auth: AlerionAuthentication,
     pub remote: String,
+    #[serde(default)]
+    pub crash_detection: AlerionCrashDetection,
+    #[serde(default)]
+    pub throttles: AlerionThrottles,
+    #[serde(default)]
+    pub docker: AlerionDocker,
+    #[serde(default)]
+    pub user: AlerionUser,
+    /// How many past console lines are kept per server and sent to websocket
+    /// clients asking for logs.
+    #[serde(default = "default_websocket_log_count")]
+    pub websocket_log_count: usize,
+    /// Most console lines `GET /api/servers/{uuid}/logs` returns at once.
+    #[serde(default = "default_max_log_lines")]
+    pub max_log_lines: usize,
+    /// Whether the data of deleted servers is moved to a trash directory
+    /// instead of being erased.
+    #[serde(default)]
+    pub keep_deleted_server_data: bool,
+    /// Seconds a server gets to shut down after being asked to stop before
+    /// its container is killed.
+    #[serde(default = "default_stop_timeout")]
+    pub stop_timeout: u64,
+}
+
+fn default_websocket_log_count() -> usize {
+    150
+}
+
+fn default_max_log_lines() -> usize {
+    100
+}
+
+fn default_stop_timeout() -> u64 {
+    600
 }
 
 impl AlerionConfig {
@@ -3,7 +3,9 @@ use std::net::{IpAddr, Ipv4Addr};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use super::{AlerionApi, AlerionApiSsl, AlerionAuthentication, AlerionConfig};
+use super::{
+    AlerionApi, AlerionApiSsl, AlerionAuthentication, AlerionConfig, AlerionCrashDetection, AlerionDocker, AlerionThrottles, AlerionUser
+};
 
 pub const WINGS_CONFIG_PATH: &str = "/etc/pterodactyl/config.yml";
 
@@ -216,11 +218,44 @@ impl From<Config> for AlerionConfig {
                 cert: root.api.ssl.cert,
                 key: root.api.ssl.key,
             },
+            allowed_origins: root
+                .allowed_origins
+                .iter()
+                .filter_map(|origin| origin.as_str().map(ToOwned::to_owned))
+                .collect(),
+            allow_cors_private_network: root.allow_cors_private_network,
         };
 
         let auth = AlerionAuthentication {
             token: root.token,
             token_id: root.token_id,
+            extra_tokens: Vec::new(),
+        };
+
+        let crash_detection = AlerionCrashDetection {
+            enabled: root.system.crash_detection.enabled,
+            detect_clean_exit_as_crash: root.system.crash_detection.detect_clean_exit_as_crash,
+            timeout: root.system.crash_detection.timeout.max(0) as u64,
+        };
+
+        let throttles = AlerionThrottles {
+            enabled: root.throttles.enabled,
+            lines: root.throttles.lines.max(0) as u64,
+            line_reset_interval: root.throttles.line_reset_interval.max(0) as u64,
+            ..AlerionThrottles::default()
+        };
+
+        let docker = if root.docker.network.interface.is_empty() {
+            AlerionDocker::default()
+        } else {
+            AlerionDocker {
+                interface: root.docker.network.interface,
+            }
+        };
+
+        let user = AlerionUser {
+            uid: root.system.user.uid.max(0) as u32,
+            gid: root.system.user.gid.max(0) as u32,
         };
 
         AlerionConfig {
@@ -229,6 +264,14 @@ impl From<Config> for AlerionConfig {
             uuid: root.uuid,
             api,
             auth,
+            crash_detection,
+            throttles,
+            docker,
+            user,
+            websocket_log_count: root.system.websocket_log_count.max(0) as usize,
+            max_log_lines: super::default_max_log_lines(),
+            keep_deleted_server_data: false,
+            stop_timeout: super::default_stop_timeout(),
         }
     }
 }
@@ -1,6 +1,12 @@
+use std::io;
+use std::os::unix::fs::lchown;
+use std::path::{Path, PathBuf};
+
 use anyhow::Context;
 use directories::ProjectDirs;
 
+use crate::config::AlerionUser;
+
 #[tracing::instrument]
 pub async fn setup_directories() -> anyhow::Result<ProjectDirs> {
     let project_dirs = ProjectDirs::from("host", "pyro", "alerion")
@@ -9,8 +15,59 @@ pub async fn setup_directories() -> anyhow::Result<ProjectDirs> {
     tokio::fs::create_dir_all(project_dirs.config_dir()).await?;
     tokio::fs::create_dir_all(project_dirs.data_dir()).await?;
     tokio::fs::create_dir_all(project_dirs.cache_dir()).await?;
+    tokio::fs::create_dir_all(volumes_dir(&project_dirs)).await?;
+    tokio::fs::create_dir_all(console_logs_dir(&project_dirs)).await?;
 
     tracing::info!("Directories created");
 
     Ok(project_dirs)
 }
+
+/// Directory holding one data volume per server.
+pub fn volumes_dir(project_dirs: &ProjectDirs) -> PathBuf {
+    project_dirs.data_dir().join("volumes")
+}
+
+/// Directory holding the installation scripts of servers being installed.
+pub fn install_scripts_dir(project_dirs: &ProjectDirs) -> PathBuf {
+    project_dirs.cache_dir().join("install")
+}
+
+/// Directory holding the daemon's copy of each server's console output.
+pub fn console_logs_dir(project_dirs: &ProjectDirs) -> PathBuf {
+    project_dirs.data_dir().join("logs")
+}
+
+/// Directory the data of deleted servers is moved to, when it is kept.
+pub fn trash_dir(project_dirs: &ProjectDirs) -> PathBuf {
+    project_dirs.data_dir().join("trash")
+}
+
+/// Gives `path` to `user`. A symbolic link is changed itself, not followed.
+pub fn chown(path: &Path, user: AlerionUser) -> io::Result<()> {
+    lchown(path, Some(user.uid), Some(user.gid))
+}
+
+/// Gives `root` and everything under it to `user`, without following
+/// symbolic links.
+pub async fn chown_recursive(root: &Path, user: AlerionUser) -> io::Result<()> {
+    let root = root.to_path_buf();
+
+    tokio::task::spawn_blocking(move || {
+        let mut pending = vec![root];
+
+        while let Some(path) = pending.pop() {
+            chown(&path, user)?;
+
+            if std::fs::symlink_metadata(&path)?.is_dir() {
+                for entry in std::fs::read_dir(&path)? {
+                    pending.push(entry?.path());
+                }
+            }
+        }
+
+        Ok(())
+    })
+    .await
+    .map_err(io::Error::other)?
+}
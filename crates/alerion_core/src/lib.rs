@@ -39,7 +39,7 @@ pub async fn alerion_main() -> anyhow::Result<()> {
     let project_dirs = setup_directories().await?;
     let config = AlerionConfig::load(&project_dirs)?;
 
-    let server_pool = Arc::new(ServerPool::new(&config).await?);
+    let server_pool = Arc::new(ServerPool::new(&config, &project_dirs).await?);
 
     //server_pool.create_server("0e4059ca-d79b-46a5-8ec4-95bd0736d150".try_into().unwrap()).await;
 
@@ -69,5 +69,6 @@ pub async fn alerion_main() -> anyhow::Result<()> {
 pub mod config;
 pub mod filesystem;
 pub mod servers;
+pub mod templating;
 pub mod webserver;
 pub mod websocket;
@@ -1,18 +1,38 @@
 use std::collections::HashMap;
-use std::sync::atomic::{AtomicU32, Ordering};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::Instant;
-
-use alerion_datamodel::remote::server::{ContainerConfig, ServerSettings};
-use bollard::container::{Config, CreateContainerOptions};
+use std::time::{Duration, Instant, SystemTime};
+
+use alerion_datamodel::remote::server::{ProcessConfig, ServerSettings};
+use alerion_datamodel::webserver::ServerDetails;
+use alerion_datamodel::websocket::{
+    PerformanceStatisics, PowerAction, ServerStatus, WebsocketEvent
+};
+use bollard::container::{
+    AttachContainerOptions, CreateContainerOptions, LogOutput, RemoveContainerOptions
+};
+use bollard::errors::Error as DockerError;
+use bollard::image::CreateImageOptions;
 use bollard::Docker;
-use serde::{Deserialize, Serialize};
+use directories::ProjectDirs;
+use futures::StreamExt;
 use thiserror::Error;
-use tokio::sync::{mpsc, Mutex, RwLock};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, watch, Mutex, RwLock};
+use tokio::task::JoinHandle;
 use uuid::Uuid;
 
+use self::console::{ConsoleHistory, ConsoleThrottle, LineSplitter, StartupDetector, Throttled};
 use crate::config::AlerionConfig;
-use crate::webserver::websocket::SendWebsocketEvent;
+use crate::filesystem;
+use crate::templating::TemplateContext;
+
+/// Longest a websocket token stays valid. The panel issues them for ten
+/// minutes; this leaves plenty of margin.
+const MAX_TOKEN_LIFETIME: Duration = Duration::from_secs(60 * 60);
 
 #[derive(Debug, Error)]
 pub enum ServerError {
@@ -20,17 +40,36 @@ pub enum ServerError {
     Docker(#[from] bollard::errors::Error),
     #[error("panel remote API error: {0}")]
     RemoteApi(#[from] remote::ResponseError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("another power action is currently being processed for this server")]
+    PowerActionInProgress,
+    #[error("server is already running")]
+    AlreadyRunning,
+    #[error("server is not running")]
+    NotRunning,
+    #[error("server is already being installed")]
+    InstallInProgress,
+    #[error("server is suspended")]
+    Suspended,
+    #[error("server is being deleted")]
+    Deleting,
 }
 
 pub struct ServerPool {
     servers: RwLock<HashMap<Uuid, Arc<Server>>>,
     remote_api: Arc<remote::RemoteClient>,
     docker: Arc<Docker>,
+    project_dirs: ProjectDirs,
+    config: Arc<AlerionConfig>,
 }
 
 impl ServerPool {
-    #[tracing::instrument(skip(config))]
-    pub async fn new(config: &AlerionConfig) -> Result<Self, ServerError> {
+    #[tracing::instrument(skip(config, project_dirs))]
+    pub async fn new(
+        config: &AlerionConfig,
+        project_dirs: &ProjectDirs,
+    ) -> Result<Self, ServerError> {
         tracing::info!("Initializing managed servers...");
 
         let remote_api = remote::RemoteClient::new(config)?;
@@ -42,6 +81,8 @@ impl ServerPool {
             servers: RwLock::new(HashMap::new()),
             remote_api: Arc::new(remote_api),
             docker: Arc::new(docker),
+            project_dirs: project_dirs.clone(),
+            config: Arc::new(config.clone()),
         })
     }
 
@@ -55,15 +96,19 @@ impl ServerPool {
             tracing::info!("Adding server {}...", s.uuid);
 
             let uuid = s.uuid;
-            let info = ServerInfo::from_remote_info(s.settings);
+            let info = ServerInfo::from_remote_info(s.settings, s.process_configuration);
             let server = Server::new(
                 uuid,
                 info,
                 Arc::clone(&self.remote_api),
                 Arc::clone(&self.docker),
+                &self.project_dirs,
+                Arc::clone(&self.config),
             )
             .await?;
 
+            server.attach_if_running().await?;
+
             self.servers.write().await.insert(uuid, server);
         }
 
@@ -71,7 +116,7 @@ impl ServerPool {
     }
 
     #[tracing::instrument(skip(self))]
-    pub async fn register_server(&self, uuid: Uuid, start: bool) -> Result<Arc<Server>, ServerError> {
+    pub async fn register_server(&self, uuid: Uuid) -> Result<Arc<Server>, ServerError> {
         tracing::info!("Adding server {uuid}...");
 
         let remote_api = Arc::clone(&self.remote_api);
@@ -79,39 +124,107 @@ impl ServerPool {
 
         tracing::debug!("Fetching server configuration from remote");
         let config = remote_api.get_server_configuration(uuid).await?;
-        let server_info = ServerInfo::from_remote_info(config.settings);
+        let server_info =
+            ServerInfo::from_remote_info(config.settings, config.process_configuration);
+
+        let server = Server::new(
+            uuid,
+            server_info,
+            remote_api,
+            docker,
+            &self.project_dirs,
+            Arc::clone(&self.config),
+        )
+        .await?;
+        server.attach_if_running().await?;
 
-        let server = Server::new(uuid, server_info, remote_api, docker).await?;
         self.servers.write().await.insert(uuid, Arc::clone(&server));
 
         Ok(server)
     }
 
+    /// Deletes the server and everything the daemon keeps for it. Deleting a
+    /// server that is not known succeeds, so the panel can retry safely.
+    ///
+    /// The server is no longer listed while it is torn down. It is put back,
+    /// accepting power actions and websocket sessions again and measuring its
+    /// disk usage, if that fails, so the deletion can be attempted again.
+    #[tracing::instrument(skip(self))]
+    pub async fn remove_server(&self, uuid: Uuid) -> Result<(), ServerError> {
+        let Some(server) = self.servers.write().await.remove(&uuid) else {
+            return Ok(());
+        };
+
+        let trash = self
+            .config
+            .keep_deleted_server_data
+            .then(|| filesystem::trash_dir(&self.project_dirs));
+
+        if let Err(e) = server.delete(trash.as_deref()).await {
+            server.deleting.store(false, Ordering::SeqCst);
+            server.spawn_disk_usage_sampler().await;
+            self.servers.write().await.entry(uuid).or_insert(server);
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
     pub async fn get_server(&self, uuid: Uuid) -> Option<Arc<Server>> {
         self.servers.read().await.get(&uuid).cloned()
     }
+
+    pub async fn servers(&self) -> Vec<Arc<Server>> {
+        self.servers.read().await.values().cloned().collect()
+    }
 }
 
 //TODO: Remove allow(dead_code) when implemented
 #[allow(dead_code)]
 pub struct ServerInfo {
-    container: ContainerConfig,
+    settings: ServerSettings,
+    process: ProcessConfig,
+    startup: StartupDetector,
 }
 
 impl ServerInfo {
-    pub fn from_remote_info(server_settings: ServerSettings) -> Self {
+    pub fn settings(&self) -> &ServerSettings {
+        &self.settings
+    }
+
+    pub fn from_remote_info(settings: ServerSettings, process: ProcessConfig) -> Self {
+        let startup = StartupDetector::from_config(&process.startup);
+
         Self {
-            container: server_settings.container,
+            settings,
+            process,
+            startup,
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Default, Copy, Clone, PartialEq, Eq, Hash)]
-struct IntoStringZst;
+/// IDs of denied websocket tokens, along with when each was denied.
+///
+/// The panel reuses token IDs, so only tokens issued before the denial are
+/// refused. A denial is forgotten once every token it could apply to has
+/// expired, which keeps the list from growing forever.
+#[derive(Debug, Default)]
+struct DeniedTokens(HashMap<String, SystemTime>);
+
+impl DeniedTokens {
+    fn deny(&mut self, jtis: impl IntoIterator<Item = String>, now: SystemTime) {
+        self.0.retain(|_, denied_at| {
+            now.duration_since(*denied_at)
+                .map_or(true, |age| age < MAX_TOKEN_LIFETIME)
+        });
+
+        self.0.extend(jtis.into_iter().map(|jti| (jti, now)));
+    }
 
-impl From<IntoStringZst> for String {
-    fn from(_value: IntoStringZst) -> Self {
-        String::new()
+    fn is_denied(&self, jti: &str, issued_at: SystemTime) -> bool {
+        self.0
+            .get(jti)
+            .is_some_and(|denied_at| issued_at < *denied_at)
     }
 }
 
@@ -122,44 +235,426 @@ pub struct Server {
     uuid: Uuid,
     container_name: String,
     websocket_id_counter: AtomicU32,
-    websocket_connections: Mutex<HashMap<u32, mpsc::Sender<SendWebsocketEvent>>>,
-    server_info: ServerInfo,
+    websocket_connections: Mutex<HashMap<u32, mpsc::Sender<WebsocketEvent>>>,
+    denied_tokens: Mutex<DeniedTokens>,
+    token_denials: watch::Sender<()>,
+    stdin: Mutex<Option<Pin<Box<dyn AsyncWrite + Send>>>>,
+    status: watch::Sender<ServerStatus>,
+    power_lock: Arc<Mutex<()>>,
+    last_crash: Mutex<Option<Instant>>,
+    installation: Mutex<Option<JoinHandle<()>>>,
+    stats: Mutex<PerformanceStatisics>,
+    stats_sampler: Mutex<Option<JoinHandle<()>>>,
+    /// Size of the server volume in bytes, kept apart from `stats` since it
+    /// is measured whether or not the container runs.
+    disk_bytes: AtomicU64,
+    disk_usage_sampler: Mutex<Option<JoinHandle<()>>>,
+    console_history: Mutex<ConsoleHistory>,
+    console_throttle: Mutex<ConsoleThrottle>,
+    console_log: Mutex<Option<logs::ConsoleLog>>,
+    console_log_path: PathBuf,
+    server_info: watch::Sender<Arc<ServerInfo>>,
+    /// Set when the container no longer matches the server's settings and
+    /// has to be recreated before the next start.
+    rebuild_required: AtomicBool,
+    /// Set once the server starts being deleted. Power actions,
+    /// installations and new websocket sessions are refused from then on.
+    deleting: AtomicBool,
+    volume_dir: PathBuf,
+    install_dir: PathBuf,
+    config: Arc<AlerionConfig>,
     remote_api: Arc<remote::RemoteClient>,
     docker: Arc<Docker>,
 }
 
 impl Server {
-    #[tracing::instrument(skip(server_info, remote_api, docker))]
+    #[tracing::instrument(skip(server_info, remote_api, docker, project_dirs, config))]
     pub async fn new(
         uuid: Uuid,
         server_info: ServerInfo,
         remote_api: Arc<remote::RemoteClient>,
         docker: Arc<Docker>,
+        project_dirs: &ProjectDirs,
+        config: Arc<AlerionConfig>,
     ) -> Result<Arc<Self>, ServerError> {
         tracing::debug!("Creating new server {uuid}");
 
+        let dir_name = uuid.as_hyphenated().to_string();
+
         let server = Arc::new(Self {
             start_time: Instant::now(),
             uuid,
             container_name: format!("{}_container", uuid.as_hyphenated()),
             websocket_id_counter: AtomicU32::new(0),
             websocket_connections: Mutex::new(HashMap::new()),
-            server_info,
+            denied_tokens: Mutex::new(DeniedTokens::default()),
+            token_denials: watch::Sender::new(()),
+            stdin: Mutex::new(None),
+            status: watch::Sender::new(ServerStatus::Offline),
+            power_lock: Arc::new(Mutex::new(())),
+            last_crash: Mutex::new(None),
+            installation: Mutex::new(None),
+            stats: Mutex::new(PerformanceStatisics::default()),
+            stats_sampler: Mutex::new(None),
+            disk_bytes: AtomicU64::new(0),
+            disk_usage_sampler: Mutex::new(None),
+            console_history: Mutex::new(ConsoleHistory::new(config.websocket_log_count)),
+            console_throttle: Mutex::new(ConsoleThrottle::new(config.throttles.clone())),
+            console_log: Mutex::new(None),
+            console_log_path: filesystem::console_logs_dir(project_dirs)
+                .join(format!("{dir_name}.log")),
+            rebuild_required: AtomicBool::new(server_info.settings.container.requires_rebuild),
+            server_info: watch::Sender::new(Arc::new(server_info)),
+            deleting: AtomicBool::new(false),
+            volume_dir: filesystem::volumes_dir(project_dirs).join(&dir_name),
+            install_dir: filesystem::install_scripts_dir(project_dirs).join(&dir_name),
+            config,
             remote_api,
             docker,
         });
 
+        server.spawn_disk_usage_sampler().await;
+
         Ok(server)
     }
 
-    pub async fn add_websocket_connection(&self) -> mpsc::Receiver<SendWebsocketEvent> {
+    pub fn uuid(&self) -> Uuid {
+        self.uuid
+    }
+
+    pub fn status(&self) -> ServerStatus {
+        *self.status.borrow()
+    }
+
+    /// The server's current settings and process configuration, as last
+    /// fetched from the panel.
+    pub fn info(&self) -> Arc<ServerInfo> {
+        Arc::clone(&self.server_info.borrow())
+    }
+
+    /// The server's state, latest resource usage and configuration, as
+    /// reported to the panel.
+    pub async fn details(&self) -> ServerDetails {
+        let info = self.info();
+        let settings = info.settings();
+
+        ServerDetails {
+            state: self.status(),
+            is_suspended: settings.suspended,
+            utilization: self.stats().await,
+            configuration: settings.clone(),
+        }
+    }
+
+    /// Moves the server to `status`, notifying websocket sessions if it
+    /// actually changed.
+    async fn set_status(&self, status: ServerStatus) {
+        let previous = self.status.send_replace(status);
+
+        if previous != status {
+            self.announce_status(status).await;
+        }
+    }
+
+    /// Moves the server to `status` only if it is currently in `from`.
+    async fn set_status_from(&self, from: ServerStatus, status: ServerStatus) -> bool {
+        let changed = self.status.send_if_modified(|current| {
+            if *current == from && from != status {
+                *current = status;
+                true
+            } else {
+                false
+            }
+        });
+
+        if changed {
+            self.announce_status(status).await;
+        }
+
+        changed
+    }
+
+    async fn announce_status(&self, status: ServerStatus) {
+        tracing::info!(
+            "server {} is now {}",
+            self.uuid.as_hyphenated(),
+            status.as_str()
+        );
+
+        self.send_to_websockets(WebsocketEvent::Status(status))
+            .await;
+    }
+
+    /// Registers a websocket session. Once the server is being deleted, the
+    /// session is not kept and its receiver ends right away, like the ones
+    /// the deletion closes.
+    pub async fn add_websocket_connection(&self) -> (u32, mpsc::Receiver<WebsocketEvent>) {
         let id = self.websocket_id_counter.fetch_add(1, Ordering::SeqCst);
 
         let (send, recv) = mpsc::channel(64);
 
-        self.websocket_connections.lock().await.insert(id, send);
+        let mut connections = self.websocket_connections.lock().await;
+
+        if !self.deleting.load(Ordering::SeqCst) {
+            connections.insert(id, send);
+        }
+
+        (id, recv)
+    }
+
+    pub async fn remove_websocket_connection(&self, id: u32) {
+        self.websocket_connections.lock().await.remove(&id);
+    }
+
+    /// Refuses websocket tokens with any of `jtis` as their ID that were
+    /// issued before now, and notifies live sessions so the ones using such a
+    /// token can disconnect.
+    pub async fn deny_tokens(&self, jtis: impl IntoIterator<Item = String>) {
+        self.denied_tokens
+            .lock()
+            .await
+            .deny(jtis, SystemTime::now());
+
+        self.token_denials.send_replace(());
+    }
+
+    /// Whether the token with ID `jti`, issued at `issued_at`, was denied.
+    pub async fn is_token_denied(&self, jti: &str, issued_at: SystemTime) -> bool {
+        self.denied_tokens.lock().await.is_denied(jti, issued_at)
+    }
+
+    /// Notifies the receiver every time tokens are denied.
+    pub fn subscribe_token_denials(&self) -> watch::Receiver<()> {
+        self.token_denials.subscribe()
+    }
+
+    /// Queues `event` on every websocket connection of this server. Whether a
+    /// session actually forwards it depends on its permissions.
+    pub async fn send_to_websockets(&self, event: WebsocketEvent) {
+        let mut connections = self.websocket_connections.lock().await;
+
+        connections.retain(|id, sender| match sender.try_send(event.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                tracing::debug!("websocket connection {id} is lagging behind, dropping event");
+                true
+            }
+            Err(TrySendError::Closed(_)) => false,
+        });
+    }
+
+    /// Attaches to the server's container if it is already running, which is
+    /// the case when the daemon restarts under a live server.
+    pub async fn attach_if_running(self: &Arc<Self>) -> Result<(), ServerError> {
+        let running = match self
+            .docker
+            .inspect_container(&self.container_name, None)
+            .await
+        {
+            Ok(response) => response.state.and_then(|s| s.running).unwrap_or(false),
+            Err(DockerError::DockerResponseServerError {
+                status_code: 404, ..
+            }) => false,
+            Err(e) => return Err(e.into()),
+        };
+
+        if running {
+            self.load_console_history().await?;
+            self.attach().await?;
+            self.spawn_stats_sampler().await;
+            self.set_status(ServerStatus::Running).await;
+        }
+
+        Ok(())
+    }
+
+    /// Seeds the console history with the tail of the container's logs, so
+    /// output from before the daemon started is not lost.
+    async fn load_console_history(&self) -> Result<(), ServerError> {
+        let lines = self
+            .docker_log_tail(self.config.websocket_log_count)
+            .await?;
+
+        let mut history = self.console_history.lock().await;
+
+        for line in lines {
+            history.push(line);
+        }
+
+        Ok(())
+    }
+
+    /// The latest console lines of the server, oldest first.
+    pub async fn console_history(&self) -> Vec<String> {
+        self.console_history
+            .lock()
+            .await
+            .last(self.config.websocket_log_count)
+    }
+
+    /// Attaches to the container's stdio and spawns the task that fans its
+    /// output out to websocket sessions.
+    async fn attach(self: &Arc<Self>) -> Result<(), ServerError> {
+        let opts = AttachContainerOptions::<String> {
+            stdin: Some(true),
+            stdout: Some(true),
+            stderr: Some(true),
+            stream: Some(true),
+            ..AttachContainerOptions::default()
+        };
+
+        let results = self
+            .docker
+            .attach_container(&self.container_name, Some(opts))
+            .await?;
+
+        *self.stdin.lock().await = Some(results.input);
+
+        let server = Arc::clone(self);
+        let mut output = results.output;
+
+        tokio::spawn(async move {
+            let mut splitter = LineSplitter::new();
+
+            while let Some(chunk) = output.next().await {
+                match chunk {
+                    Ok(LogOutput::StdIn { .. }) => {}
+                    Ok(log) => {
+                        for line in splitter.push(log.as_ref()) {
+                            server.handle_console_line(line).await;
+                        }
+                    }
+                    Err(e) => {
+                        tracing::warn!("error reading output of server {}: {e}", server.uuid);
+                        break;
+                    }
+                }
+            }
+
+            if let Some(line) = splitter.finish() {
+                server.handle_console_line(line).await;
+            }
+
+            *server.stdin.lock().await = None;
+            tracing::debug!("detached from container of server {}", server.uuid);
+
+            let previous = server.status.send_replace(ServerStatus::Offline);
+
+            if previous != ServerStatus::Offline {
+                server.announce_status(ServerStatus::Offline).await;
+            }
+
+            // Stops and kills go through `Stopping` first, so only a process
+            // that died on its own is still marked as running here.
+            if previous == ServerStatus::Running {
+                server.handle_crash().await;
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Writes `command` followed by a newline to the container's stdin.
+    pub async fn send_command(&self, command: &str) -> Result<(), ServerError> {
+        let mut stdin = self.stdin.lock().await;
+        let stdin = stdin.as_mut().ok_or(ServerError::NotRunning)?;
+
+        stdin.write_all(command.as_bytes()).await?;
+        stdin.write_all(b"\n").await?;
+        stdin.flush().await?;
+
+        Ok(())
+    }
+
+    /// Shows `message` in the console of every websocket session, marked as
+    /// coming from the daemon rather than the server process.
+    pub async fn send_daemon_message(&self, message: impl Into<String>) {
+        self.send_to_websockets(WebsocketEvent::DaemonMessage(message.into()))
+            .await;
+    }
+
+    async fn handle_console_line(self: &Arc<Self>, line: String) {
+        if self.status() == ServerStatus::Starting
+            && self.server_info.borrow().startup.matches(&line)
+        {
+            self.set_status_from(ServerStatus::Starting, ServerStatus::Running)
+                .await;
+        }
+
+        let throttled = self.console_throttle.lock().await.check();
+
+        match throttled {
+            Throttled::No => {}
+            Throttled::Dropping => return,
+            Throttled::Activated => {
+                self.send_daemon_message(
+                    "Server is outputting console data too quickly -- throttling...",
+                )
+                .await;
+
+                return;
+            }
+            Throttled::Exceeded => {
+                tracing::warn!(
+                    "server {} exceeded the console output throttle limit, stopping it",
+                    self.uuid.as_hyphenated()
+                );
+
+                self.send_daemon_message(
+                    "Server is being stopped for outputting console data too quickly.",
+                )
+                .await;
+
+                if let Err(e) = self.power(PowerAction::Stop) {
+                    tracing::debug!("could not stop throttled server {}: {e}", self.uuid);
+                }
+
+                return;
+            }
+        }
+
+        self.append_console_log(&line).await;
+        self.console_history.lock().await.push(line.clone());
 
-        recv
+        self.send_to_websockets(WebsocketEvent::ConsoleOutput(line))
+            .await;
+    }
+
+    /// Creates the server's container unless it already exists.
+    async fn ensure_docker_container(&self) -> Result<(), ServerError> {
+        match self
+            .docker
+            .inspect_container(&self.container_name, None)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(DockerError::DockerResponseServerError {
+                status_code: 404, ..
+            }) => self.create_docker_container().await.map(|_| ()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Removes the server's container, if there is one, killing it first if
+    /// it is still running.
+    async fn remove_docker_container(&self) -> Result<(), ServerError> {
+        let opts = RemoveContainerOptions {
+            force: true,
+            v: true,
+            ..RemoveContainerOptions::default()
+        };
+
+        match self
+            .docker
+            .remove_container(&self.container_name, Some(opts))
+            .await
+        {
+            Ok(())
+            | Err(DockerError::DockerResponseServerError {
+                status_code: 404, ..
+            }) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
     }
 
     async fn create_docker_container(&self) -> Result<String, ServerError> {
@@ -168,17 +663,24 @@ impl Server {
             self.uuid.as_hyphenated()
         );
 
+        tokio::fs::create_dir_all(&self.volume_dir).await?;
+        filesystem::chown(&self.volume_dir, self.config.user)?;
+
+        let info = self.info();
+        self.pull_image(&info.settings.container.image).await;
+
         let opts = CreateContainerOptions {
             name: self.container_name.clone(),
             platform: None,
         };
 
-        let config: Config<IntoStringZst> = Config {
-            attach_stdin: Some(true),
-            attach_stdout: Some(true),
-            attach_stderr: Some(true),
-            ..Config::default()
-        };
+        let template = self.template_context(&info.settings);
+        let config = container::container_config(
+            &info.settings,
+            &template,
+            &self.volume_dir,
+            self.config.user,
+        );
 
         let response = self.docker.create_container(Some(opts), config).await?;
 
@@ -187,9 +689,75 @@ impl Server {
         Ok(response.id)
     }
 
+    /// Pulls `image` so containers run the latest version of it. Failures are
+    /// only logged, since a local copy of the image may still be usable.
+    async fn pull_image(&self, image: &str) {
+        let opts = CreateImageOptions {
+            from_image: image,
+            ..CreateImageOptions::default()
+        };
+
+        let mut pull = self.docker.create_image(Some(opts), None, None);
+
+        while let Some(result) = pull.next().await {
+            if let Err(e) = result {
+                tracing::warn!("failed to pull image {image}: {e}");
+                break;
+            }
+        }
+    }
+
+    /// Placeholder resolution against this server's settings and the node
+    /// configuration.
+    pub fn template_context<'a>(&'a self, settings: &'a ServerSettings) -> TemplateContext<'a> {
+        TemplateContext::new(settings, &self.config)
+    }
+
     pub fn server_time(&self) -> u64 {
         self.start_time.elapsed().as_millis() as u64
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_tokens_issued_before_the_denial_are_denied() {
+        let denied_at = SystemTime::now();
+        let mut denied = DeniedTokens::default();
+        denied.deny(["jti".to_owned()], denied_at);
+
+        assert!(denied.is_denied("jti", denied_at - Duration::from_secs(1)));
+        assert!(!denied.is_denied("jti", denied_at));
+        assert!(!denied.is_denied("jti", denied_at + Duration::from_secs(1)));
+        assert!(!denied.is_denied("other", denied_at - Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn denials_are_forgotten_once_their_tokens_expired() {
+        let first = SystemTime::now();
+        let mut denied = DeniedTokens::default();
+        denied.deny(["old".to_owned()], first);
+
+        let later = first + MAX_TOKEN_LIFETIME;
+        denied.deny(["new".to_owned()], later);
+
+        assert!(!denied.0.contains_key("old"));
+        assert!(denied.is_denied("new", first));
+    }
+}
+
+pub mod configs;
+pub mod console;
+pub mod container;
+pub mod crash;
+pub mod delete;
+pub mod install;
+pub mod logs;
+pub mod power;
 pub mod remote;
+pub mod stats;
+#[cfg(test)]
+pub(crate) mod test_support;
+pub mod sync;
@@ -0,0 +1,611 @@
+use std::ffi::{OsStr, OsString};
+use std::fs::Metadata;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+
+use alerion_datamodel::remote::server::{FileParser, SearchReplaceMatcher};
+use serde_json::Value;
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use super::Server;
+use crate::config::AlerionUser;
+use crate::filesystem;
+use crate::templating::{value_to_string, TemplateContext};
+
+#[derive(Debug, Error)]
+pub enum ConfigFileError {
+    #[error("unknown configuration file parser {0:?}")]
+    UnknownParser(String),
+    #[error("configuration file path {0:?} is outside of the server volume")]
+    InvalidPath(String),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid YAML: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("invalid XML: {0}")]
+    Xml(#[from] xmltree::ParseError),
+    #[error("failed to write XML: {0}")]
+    XmlWrite(#[from] xmltree::Error),
+}
+
+/// The file formats an egg can ask to have rewritten before the server
+/// starts, named as in [`FileParser::parser`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    File,
+    Properties,
+    Ini,
+    Json,
+    Yaml,
+    Xml,
+}
+
+impl ConfigFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "file" => Some(ConfigFormat::File),
+            "properties" => Some(ConfigFormat::Properties),
+            "ini" => Some(ConfigFormat::Ini),
+            "json" => Some(ConfigFormat::Json),
+            "yaml" => Some(ConfigFormat::Yaml),
+            "xml" => Some(ConfigFormat::Xml),
+            _ => None,
+        }
+    }
+}
+
+/// A single value to write into a configuration file.
+///
+/// What `key` designates depends on the format: a line prefix for plain
+/// files, a property name, a `section.key` pair for INI files, or a
+/// JSONPath-style path for structured formats (see [`parse_path`]).
+#[derive(Debug, Clone)]
+pub struct Replacement {
+    pub key: String,
+    pub value: String,
+    /// Only replace a current value equal to this, or matching the regex
+    /// after a `regex:` prefix. Only structured formats support conditions.
+    pub condition: Option<String>,
+}
+
+/// One step of a structured key path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathSegment<'a> {
+    /// An object key or element name.
+    Key(&'a str),
+    /// A position in an array, or among same-named elements.
+    Index(usize),
+    /// Every key or element at that level.
+    Wildcard,
+}
+
+/// Splits a JSONPath-style key such as `listeners[0].host` into its steps.
+/// Segments are separated by dots, may be followed by `[i]` indexes, and `*`
+/// or `[*]` matches everything at that level.
+pub fn parse_path(key: &str) -> Vec<PathSegment<'_>> {
+    let mut segments = Vec::new();
+
+    for part in key.split('.') {
+        let (name, mut indexes) = part.split_at(part.find('[').unwrap_or(part.len()));
+
+        match name {
+            "" => {}
+            "*" => segments.push(PathSegment::Wildcard),
+            name => segments.push(PathSegment::Key(name)),
+        }
+
+        while let Some((index, rest)) = indexes
+            .strip_prefix('[')
+            .and_then(|rest| rest.split_once(']'))
+        {
+            segments.push(match index.trim() {
+                "*" => PathSegment::Wildcard,
+                index => index
+                    .parse()
+                    .map_or(PathSegment::Key(index), PathSegment::Index),
+            });
+
+            indexes = rest;
+        }
+    }
+
+    segments
+}
+
+/// Applies `replacements` to `contents`, a file in the given format, and
+/// returns the rewritten file.
+pub fn apply(
+    format: ConfigFormat,
+    contents: &str,
+    replacements: &[Replacement],
+) -> Result<String, ConfigFileError> {
+    let unconditional;
+
+    let replacements = match format {
+        ConfigFormat::Json | ConfigFormat::Yaml => replacements,
+        _ => {
+            unconditional = without_conditions(replacements);
+            &unconditional
+        }
+    };
+
+    match format {
+        ConfigFormat::File => Ok(plain::apply(contents, replacements)),
+        ConfigFormat::Properties => Ok(properties::apply(contents, replacements)),
+        ConfigFormat::Ini => Ok(ini::apply(contents, replacements)),
+        ConfigFormat::Json => structured::apply_json(contents, replacements),
+        ConfigFormat::Yaml => structured::apply_yaml(contents, replacements),
+        ConfigFormat::Xml => xml::apply(contents, replacements),
+    }
+}
+
+/// Drops the conditional replacements, which only structured formats
+/// support.
+fn without_conditions(replacements: &[Replacement]) -> Vec<Replacement> {
+    let (conditional, unconditional): (Vec<_>, Vec<_>) = replacements
+        .iter()
+        .cloned()
+        .partition(|r| r.condition.is_some());
+
+    for replacement in conditional {
+        tracing::warn!(
+            "skipping conditional replacement of {}, only JSON and YAML files support conditions",
+            replacement.key
+        );
+    }
+
+    unconditional
+}
+
+/// Turns an egg's `replace` entries into replacements, rendering the
+/// placeholders in their values. An entry that cannot be used is logged and
+/// skipped, so it only loses its own value and not the rest of the file.
+pub fn replacements(
+    matchers: &[SearchReplaceMatcher],
+    template: &TemplateContext,
+    file: &str,
+) -> Vec<Replacement> {
+    let mut replacements = Vec::new();
+
+    for matcher in matchers {
+        let candidates: Vec<(Option<&String>, &Value)> = match &matcher.replace_with {
+            Value::Object(conditions) => conditions
+                .iter()
+                .map(|(condition, value)| (Some(condition), value))
+                .collect(),
+            value => vec![(matcher.if_value.as_ref(), value)],
+        };
+
+        for (condition, value) in candidates {
+            let rendered = match value {
+                Value::String(value) => template.render(value).map_err(|e| e.to_string()),
+                Value::Bool(_) | Value::Number(_) => Ok(value_to_string(value)),
+                _ => Err(format!("unsupported value {value}")),
+            };
+
+            match rendered {
+                Ok(value) => replacements.push(Replacement {
+                    key: matcher.match_item.clone(),
+                    value,
+                    condition: condition.cloned(),
+                }),
+                Err(e) => tracing::warn!(
+                    "skipping {} in configuration file {file}: {e}",
+                    matcher.match_item
+                ),
+            }
+        }
+    }
+
+    replacements
+}
+
+/// Resolves `file`, a path relative to the server volume, into its canonical
+/// parent directory and file name. Missing directories are created one at a
+/// time, owned by `user`, and each is canonicalized, so neither `..` nor a
+/// symlink planted in the volume can lead outside of it.
+async fn resolve_path(
+    volume_dir: &Path,
+    file: &str,
+    user: AlerionUser,
+) -> Result<(PathBuf, OsString), ConfigFileError> {
+    let invalid = || ConfigFileError::InvalidPath(file.to_owned());
+    let relative = Path::new(file.trim_start_matches('/'));
+
+    let safe = relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+
+    let name = relative.file_name().filter(|_| safe).ok_or_else(invalid)?;
+
+    let volume = tokio::fs::canonicalize(volume_dir).await?;
+    let mut dir = volume.clone();
+
+    for component in relative.parent().into_iter().flat_map(Path::components) {
+        let Component::Normal(part) = component else {
+            continue;
+        };
+
+        let next = dir.join(part);
+
+        match tokio::fs::create_dir(&next).await {
+            Ok(()) => filesystem::chown(&next, user)?,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        dir = tokio::fs::canonicalize(&next).await?;
+
+        if !dir.starts_with(&volume) {
+            return Err(invalid());
+        }
+    }
+
+    Ok((dir, name.to_owned()))
+}
+
+/// Reads the regular file at `path` without following a symlink in its
+/// place, returning `None` if it does not exist yet.
+async fn read_regular_file(path: &Path) -> Result<Option<(String, Metadata)>, ConfigFileError> {
+    let invalid = || ConfigFileError::InvalidPath(path.display().to_string());
+
+    // O_NONBLOCK keeps a FIFO from hanging the open; it is refused below.
+    let open = tokio::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+        .open(path)
+        .await;
+
+    let mut file = match open {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) if e.raw_os_error() == Some(libc::ELOOP) => return Err(invalid()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let metadata = file.metadata().await?;
+
+    if !metadata.is_file() {
+        return Err(invalid());
+    }
+
+    let mut contents = String::new();
+    file.read_to_string(&mut contents).await?;
+
+    Ok(Some((contents, metadata)))
+}
+
+/// Replaces `dir/name` with `contents` by writing a temporary file next to it
+/// and renaming it into place, so a symlink at `name` is replaced rather than
+/// followed. The previous file's mode and owner are kept, and a new file is
+/// owned by `user`.
+async fn write_replacing(
+    dir: &Path,
+    name: &OsStr,
+    contents: &str,
+    previous: Option<&Metadata>,
+    user: AlerionUser,
+) -> Result<(), ConfigFileError> {
+    let mut temp_name = OsString::from(".");
+    temp_name.push(name);
+    temp_name.push(".alerion-tmp");
+    let temp = dir.join(temp_name);
+
+    // Left over from an interrupted write.
+    match tokio::fs::remove_file(&temp).await {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    let written = async {
+        let mut file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .custom_flags(libc::O_NOFOLLOW)
+            .mode(0o644)
+            .open(&temp)
+            .await?;
+
+        file.write_all(contents.as_bytes()).await?;
+
+        match previous {
+            Some(previous) => {
+                file.set_permissions(previous.permissions()).await?;
+                std::os::unix::fs::fchown(&file, Some(previous.uid()), Some(previous.gid()))?;
+            }
+            None => std::os::unix::fs::fchown(&file, Some(user.uid), Some(user.gid))?,
+        }
+
+        file.sync_all().await?;
+        drop(file);
+
+        tokio::fs::rename(&temp, dir.join(name)).await
+    }
+    .await;
+
+    if written.is_err() {
+        let _ = tokio::fs::remove_file(&temp).await;
+    }
+
+    Ok(written?)
+}
+
+/// Applies `replacements` to `file`, a path relative to `volume_dir`, creating
+/// it for `user` if needed, and returns where it was written.
+async fn rewrite_file(
+    volume_dir: &Path,
+    file: &str,
+    format: ConfigFormat,
+    replacements: &[Replacement],
+    user: AlerionUser,
+) -> Result<PathBuf, ConfigFileError> {
+    let (dir, name) = resolve_path(volume_dir, file, user).await?;
+    let path = dir.join(&name);
+
+    let previous = read_regular_file(&path).await?;
+    let contents = previous
+        .as_ref()
+        .map_or("", |(contents, _)| contents.as_str());
+
+    let updated = apply(format, contents, replacements)?;
+
+    write_replacing(
+        &dir,
+        &name,
+        &updated,
+        previous.as_ref().map(|(_, m)| m),
+        user,
+    )
+    .await?;
+
+    Ok(path)
+}
+
+impl Server {
+    /// Rewrites every configuration file listed in the egg's process
+    /// configuration. A file that fails to update is logged and skipped so it
+    /// does not prevent the server from starting.
+    pub(super) async fn update_config_files(&self) {
+        let info = self.info();
+
+        for parser in &info.process.configs {
+            if let Err(e) = self.update_config_file(parser).await {
+                tracing::warn!(
+                    "failed to update configuration file {} of server {}: {e}",
+                    parser.file,
+                    self.uuid.as_hyphenated()
+                );
+            }
+        }
+    }
+
+    async fn update_config_file(&self, parser: &FileParser) -> Result<(), ConfigFileError> {
+        let format = ConfigFormat::from_name(&parser.parser)
+            .ok_or_else(|| ConfigFileError::UnknownParser(parser.parser.clone()))?;
+
+        let info = self.info();
+        let template = self.template_context(&info.settings);
+        let replacements = replacements(&parser.replace, &template, &parser.file);
+
+        let path = rewrite_file(
+            &self.volume_dir,
+            &parser.file,
+            format,
+            &replacements,
+            self.config.user,
+        )
+        .await?;
+
+        tracing::debug!("updated configuration file {}", path.display());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PathSegment::{Index, Key, Wildcard};
+    use super::*;
+    use crate::servers::test_support;
+
+    /// Shorthand for building a [`Replacement`] in the parser tests.
+    pub(super) fn replace(key: &str, value: &str) -> Replacement {
+        Replacement {
+            key: key.to_owned(),
+            value: value.to_owned(),
+            condition: None,
+        }
+    }
+
+    #[test]
+    fn bungeecord_egg() {
+        // As sent by the panel for the BungeeCord egg.
+        let parser: FileParser = serde_json::from_value(serde_json::json!({
+            "file": "config.yml",
+            "parser": "yaml",
+            "replace": [
+                { "match": "listeners[0].query_enabled", "replace_with": true },
+                { "match": "listeners[0].query_port", "replace_with": "{{server.build.default.port}}" },
+                { "match": "listeners[0].host", "replace_with": "0.0.0.0:{{server.build.default.port}}" },
+                {
+                    "match": "servers.*.address",
+                    "replace_with": {
+                        "regex:^(127\\.0\\.0\\.1|localhost)(:\\d{1,5})?$": "{{config.docker.interface}}$2"
+                    }
+                }
+            ]
+        }))
+        .expect("valid file parser");
+
+        let (settings, config) = (test_support::settings(), test_support::config());
+        let template = TemplateContext::new(&settings, &config);
+        let replacements = replacements(&parser.replace, &template, &parser.file);
+
+        let contents = "\
+listeners:
+- query_port: 25577
+  motd: '&1Another Bungee server'
+  query_enabled: false
+  host: 0.0.0.0:25577
+servers:
+  lobby:
+    motd: '&1Just another BungeeCord - Forced Host'
+    address: localhost:25566
+    restricted: false
+  external:
+    address: play.example.com:25565
+";
+        let out = apply(ConfigFormat::Yaml, contents, &replacements).expect("valid YAML");
+        let value: Value = serde_yaml::from_str(&out).expect("valid YAML output");
+
+        assert_eq!(value["listeners"][0]["query_enabled"], true);
+        assert_eq!(value["listeners"][0]["query_port"], 25565);
+        assert_eq!(value["listeners"][0]["host"], "0.0.0.0:25565");
+        assert_eq!(value["servers"]["lobby"]["address"], "172.18.0.1:25566");
+        assert_eq!(
+            value["servers"]["external"]["address"],
+            "play.example.com:25565"
+        );
+    }
+
+    #[test]
+    fn conditions_are_skipped_outside_structured_formats() {
+        let replacements = [
+            replace("a", "1"),
+            Replacement {
+                condition: Some("0".to_owned()),
+                ..replace("b", "2")
+            },
+        ];
+
+        let out = apply(ConfigFormat::Properties, "b=0\n", &replacements).expect("plain text");
+
+        assert_eq!(out, "b=0\na=1\n");
+    }
+
+    #[test]
+    fn parses_dotted_paths() {
+        assert_eq!(parse_path("server.port"), [Key("server"), Key("port")]);
+        assert_eq!(
+            parse_path("servers.*.motd"),
+            [Key("servers"), Wildcard, Key("motd")]
+        );
+    }
+
+    #[test]
+    fn parses_indexes() {
+        assert_eq!(
+            parse_path("listeners[0].host"),
+            [Key("listeners"), Index(0), Key("host")]
+        );
+        assert_eq!(parse_path("a[1][*]"), [Key("a"), Index(1), Wildcard]);
+    }
+
+    /// Runs `rewrite_file` in a fresh directory laid out as `root/volume`,
+    /// after `setup` has populated it.
+    fn rewrite_in(
+        name: &str,
+        file: &str,
+        setup: impl FnOnce(&Path),
+    ) -> (PathBuf, Result<PathBuf, ConfigFileError>) {
+        let root =
+            std::env::temp_dir().join(format!("alerion-configs-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("volume")).expect("writable temp dir");
+        setup(&root);
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .expect("runtime");
+        let replacements = [replace("server-port", "25570")];
+        let result = runtime.block_on(rewrite_file(
+            &root.join("volume"),
+            file,
+            ConfigFormat::Properties,
+            &replacements,
+            owner(&root),
+        ));
+
+        (root, result)
+    }
+
+    /// The owner of `path`, which the tests can chown to without privileges.
+    fn owner(path: &Path) -> AlerionUser {
+        let metadata = std::fs::metadata(path).expect("readable temp dir");
+
+        AlerionUser {
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+        }
+    }
+
+    #[test]
+    fn rewrites_files_inside_the_volume() {
+        let (root, result) = rewrite_in("inside", "config/server.properties", |_| {});
+
+        let written = result.expect("file inside the volume");
+        assert!(written.ends_with("config/server.properties"));
+        assert_eq!(
+            std::fs::read_to_string(root.join("volume/config/server.properties"))
+                .expect("readable file"),
+            "server-port=25570\n"
+        );
+
+        let metadata = std::fs::metadata(&written).expect("readable file");
+        assert_eq!(metadata.uid(), owner(&root).uid);
+
+        std::fs::remove_dir_all(root).expect("removable temp dir");
+    }
+
+    #[test]
+    fn refuses_parent_components() {
+        let (root, result) = rewrite_in("parent", "../outside.properties", |_| {});
+
+        assert!(matches!(result, Err(ConfigFileError::InvalidPath(_))));
+        assert!(!root.join("outside.properties").exists());
+
+        std::fs::remove_dir_all(root).expect("removable temp dir");
+    }
+
+    #[test]
+    fn does_not_follow_a_symlinked_file() {
+        let (root, result) = rewrite_in("file-link", "server.properties", |root| {
+            std::fs::write(root.join("outside"), "secret\n").expect("writable temp dir");
+            std::os::unix::fs::symlink(root.join("outside"), root.join("volume/server.properties"))
+                .expect("symlink");
+        });
+
+        assert!(matches!(result, Err(ConfigFileError::InvalidPath(_))));
+        assert_eq!(
+            std::fs::read_to_string(root.join("outside")).expect("readable file"),
+            "secret\n"
+        );
+
+        std::fs::remove_dir_all(root).expect("removable temp dir");
+    }
+
+    #[test]
+    fn does_not_follow_a_symlinked_directory() {
+        let (root, result) = rewrite_in("dir-link", "config/server.properties", |root| {
+            std::fs::create_dir(root.join("outside")).expect("writable temp dir");
+            std::os::unix::fs::symlink(root.join("outside"), root.join("volume/config"))
+                .expect("symlink");
+        });
+
+        assert!(matches!(result, Err(ConfigFileError::InvalidPath(_))));
+        assert!(!root.join("outside/server.properties").exists());
+
+        std::fs::remove_dir_all(root).expect("removable temp dir");
+    }
+}
+
+pub mod ini;
+pub mod plain;
+pub mod properties;
+pub mod structured;
+pub mod xml;
@@ -0,0 +1,136 @@
+use super::Replacement;
+
+/// Sets INI values, keeping comments and layout. Keys are written as
+/// `section.key`, or as a bare `key` for entries above the first section.
+/// Missing keys are added at the end of their section, which is created if
+/// needed.
+pub fn apply(contents: &str, replacements: &[Replacement]) -> String {
+    let mut lines: Vec<String> = contents.lines().map(ToOwned::to_owned).collect();
+
+    for replacement in replacements {
+        let (section, key) = match replacement.key.split_once('.') {
+            Some((section, key)) => (section, key),
+            None => ("", replacement.key.as_str()),
+        };
+
+        set_value(&mut lines, section, key, &replacement.value);
+    }
+
+    let mut out = lines.join("\n");
+    out.push('\n');
+    out
+}
+
+fn set_value(lines: &mut Vec<String>, section: &str, key: &str, value: &str) {
+    let mut current = "";
+    // Index right after the last line of the target section.
+    let mut section_end = if section.is_empty() { Some(0) } else { None };
+
+    for (i, line) in lines.iter_mut().enumerate() {
+        let trimmed = line.trim();
+
+        if let Some(name) = section_name(trimmed) {
+            current = name;
+
+            if current == section {
+                section_end = Some(i + 1);
+            }
+
+            continue;
+        }
+
+        if current != section {
+            continue;
+        }
+
+        if !trimmed.is_empty() && !trimmed.starts_with([';', '#']) {
+            section_end = Some(i + 1);
+        }
+
+        let Some((line_key, _)) = trimmed.split_once('=') else {
+            continue;
+        };
+
+        if line_key.trim() == key {
+            let eq = line.find('=').unwrap_or(line.len());
+            let spacing = if line[eq + 1..].starts_with(' ') {
+                " "
+            } else {
+                ""
+            };
+
+            *line = format!("{}={spacing}{value}", &line[..eq]);
+            return;
+        }
+    }
+
+    let entry = format!("{key} = {value}");
+
+    match section_end {
+        Some(i) => lines.insert(i, entry),
+        None => {
+            if lines.last().is_some_and(|l| !l.trim().is_empty()) {
+                lines.push(String::new());
+            }
+
+            lines.push(format!("[{section}]"));
+            lines.push(entry);
+        }
+    }
+}
+
+fn section_name(line: &str) -> Option<&str> {
+    line.strip_prefix('[')?.strip_suffix(']').map(str::trim)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::servers::configs::tests::replace;
+
+    #[test]
+    fn replaces_keeping_spacing() {
+        let contents = "; comment\n[server]\nport = 1\nname=old\n";
+        let out = apply(
+            contents,
+            &[replace("server.port", "2"), replace("server.name", "new")],
+        );
+
+        assert_eq!(out, "; comment\n[server]\nport = 2\nname=new\n");
+    }
+
+    #[test]
+    fn adds_missing_keys_to_their_section() {
+        let contents = "[a]\nx = 1\n\n[b]\ny = 2\n";
+        let out = apply(contents, &[replace("a.z", "3")]);
+
+        assert_eq!(out, "[a]\nx = 1\nz = 3\n\n[b]\ny = 2\n");
+    }
+
+    #[test]
+    fn creates_missing_sections() {
+        let out = apply("[a]\nx = 1\n", &[replace("b.y", "2")]);
+
+        assert_eq!(out, "[a]\nx = 1\n\n[b]\ny = 2\n");
+    }
+
+    #[test]
+    fn bare_keys_go_above_the_first_section() {
+        let out = apply("[a]\nx = 1\n", &[replace("top", "yes")]);
+
+        assert_eq!(out, "top = yes\n[a]\nx = 1\n");
+    }
+
+    #[test]
+    fn empty_file() {
+        assert_eq!(apply("", &[replace("s.k", "v")]), "[s]\nk = v\n");
+    }
+
+    #[test]
+    fn same_key_in_other_sections_is_left_alone() {
+        let contents = "[a]\nport = 1\n[b]\nport = 2\n";
+        let out = apply(contents, &[replace("b.port", "3")]);
+
+        assert_eq!(out, "[a]\nport = 1\n[b]\nport = 3\n");
+    }
+}
@@ -0,0 +1,18 @@
+use super::Replacement;
+
+/// Replaces every line starting with a replacement's key with its value.
+pub fn apply(contents: &str, replacements: &[Replacement]) -> String {
+    let mut out = String::with_capacity(contents.len());
+
+    for line in contents.lines() {
+        let replaced = replacements
+            .iter()
+            .find(|r| line.starts_with(r.key.as_str()))
+            .map_or(line, |r| r.value.as_str());
+
+        out.push_str(replaced);
+        out.push('\n');
+    }
+
+    out
+}
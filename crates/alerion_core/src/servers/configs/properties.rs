@@ -0,0 +1,76 @@
+use super::Replacement;
+
+/// Sets Java-style `key=value` properties, keeping comments and the order of
+/// existing entries. Properties that do not exist yet are appended.
+pub fn apply(contents: &str, replacements: &[Replacement]) -> String {
+    let mut lines: Vec<String> = contents.lines().map(ToOwned::to_owned).collect();
+
+    for replacement in replacements {
+        let line = format!("{}={}", replacement.key, replacement.value);
+
+        match lines
+            .iter_mut()
+            .find(|l| property_key(l) == Some(replacement.key.as_str()))
+        {
+            Some(existing) => *existing = line,
+            None => lines.push(line),
+        }
+    }
+
+    let mut out = lines.join("\n");
+    out.push('\n');
+    out
+}
+
+/// Returns the key of a property line, or `None` for blank lines and
+/// comments.
+fn property_key(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+
+    if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
+        return None;
+    }
+
+    let end = line
+        .find(|c: char| c == '=' || c == ':' || c.is_whitespace())
+        .unwrap_or(line.len());
+
+    Some(&line[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::servers::configs::tests::replace;
+
+    #[test]
+    fn replaces_existing_properties_in_place() {
+        let contents = "#Minecraft server properties\nserver-port=25565\nmotd=A Minecraft Server\n";
+        let out = apply(contents, &[replace("server-port", "25570")]);
+
+        assert_eq!(
+            out,
+            "#Minecraft server properties\nserver-port=25570\nmotd=A Minecraft Server\n"
+        );
+    }
+
+    #[test]
+    fn appends_missing_properties() {
+        let out = apply("motd=hi\n", &[replace("query.port", "25565")]);
+
+        assert_eq!(out, "motd=hi\nquery.port=25565\n");
+    }
+
+    #[test]
+    fn empty_file() {
+        assert_eq!(apply("", &[replace("a", "1")]), "a=1\n");
+    }
+
+    #[test]
+    fn comments_and_prefixes_do_not_match() {
+        let contents = "#server-port=1\nserver-port-v6=2\nserver-port : 3\n";
+        let out = apply(contents, &[replace("server-port", "4")]);
+
+        assert_eq!(out, "#server-port=1\nserver-port-v6=2\nserver-port=4\n");
+    }
+}
@@ -0,0 +1,312 @@
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use super::{parse_path, ConfigFileError, PathSegment, Replacement};
+
+pub fn apply_json(contents: &str, replacements: &[Replacement]) -> Result<String, ConfigFileError> {
+    let mut document = if contents.trim().is_empty() {
+        Value::Object(Map::new())
+    } else {
+        serde_json::from_str(contents)?
+    };
+
+    apply_to_value(&mut document, replacements);
+
+    let mut out = serde_json::to_string_pretty(&document)?;
+    out.push('\n');
+    Ok(out)
+}
+
+pub fn apply_yaml(contents: &str, replacements: &[Replacement]) -> Result<String, ConfigFileError> {
+    let mut document = if contents.trim().is_empty() {
+        Value::Object(Map::new())
+    } else {
+        serde_yaml::from_str(contents)?
+    };
+
+    apply_to_value(&mut document, replacements);
+
+    Ok(serde_yaml::to_string(&document)?)
+}
+
+/// Sets every replacement in `document`, following its key as a
+/// JSONPath-style path.
+pub fn apply_to_value(document: &mut Value, replacements: &[Replacement]) {
+    for replacement in replacements {
+        let path = parse_path(&replacement.key);
+
+        let condition = match replacement.condition.as_deref().map(Condition::parse) {
+            None => None,
+            Some(Ok(condition)) => Some(condition),
+            Some(Err(e)) => {
+                tracing::warn!("skipping {}: invalid condition: {e}", replacement.key);
+                continue;
+            }
+        };
+
+        let setter = Setter {
+            value: &replacement.value,
+            condition,
+        };
+
+        set_path(document, &path, &setter);
+    }
+}
+
+/// Interprets booleans and integers so they are not written as strings.
+fn typed_value(value: &str) -> Value {
+    if let Ok(b) = value.parse::<bool>() {
+        Value::Bool(b)
+    } else if let Ok(i) = value.parse::<i64>() {
+        Value::from(i)
+    } else {
+        Value::String(value.to_owned())
+    }
+}
+
+/// What the current value has to be for a conditional replacement to apply.
+enum Condition {
+    Equals(String),
+    /// The value is replaced with the regex's expansion of the replacement,
+    /// so `$1` and the like refer to its groups.
+    Matches(Regex),
+}
+
+impl Condition {
+    fn parse(condition: &str) -> Result<Self, regex::Error> {
+        match condition.strip_prefix("regex:") {
+            Some(pattern) => Ok(Condition::Matches(Regex::new(pattern)?)),
+            None => Ok(Condition::Equals(condition.to_owned())),
+        }
+    }
+}
+
+struct Setter<'a> {
+    value: &'a str,
+    condition: Option<Condition>,
+}
+
+impl Setter<'_> {
+    /// Conditional replacements only change values that already exist.
+    fn creates_missing(&self) -> bool {
+        self.condition.is_none()
+    }
+
+    fn set(&self, target: &mut Value) {
+        let Some(condition) = &self.condition else {
+            *target = typed_value(self.value);
+            return;
+        };
+
+        let current = match target {
+            Value::String(s) => s.clone(),
+            Value::Bool(_) | Value::Number(_) => target.to_string(),
+            _ => return,
+        };
+
+        match condition {
+            Condition::Equals(expected) => {
+                if current == *expected {
+                    *target = typed_value(self.value);
+                }
+            }
+            Condition::Matches(regex) => {
+                if regex.is_match(&current) {
+                    *target = typed_value(&regex.replace_all(&current, self.value));
+                }
+            }
+        }
+    }
+}
+
+fn set_path(target: &mut Value, path: &[PathSegment], setter: &Setter) {
+    let Some((segment, rest)) = path.split_first() else {
+        setter.set(target);
+        return;
+    };
+
+    if target.is_null() {
+        if !setter.creates_missing() {
+            return;
+        }
+
+        *target = match segment {
+            PathSegment::Index(_) => Value::Array(Vec::new()),
+            _ => Value::Object(Map::new()),
+        };
+    }
+
+    match (target, segment) {
+        (Value::Object(map), PathSegment::Wildcard) => {
+            for child in map.values_mut() {
+                set_path(child, rest, setter);
+            }
+        }
+
+        (Value::Object(map), PathSegment::Key(key)) => {
+            if let Some(child) = map.get_mut(*key) {
+                set_path(child, rest, setter);
+            } else if setter.creates_missing() {
+                let child = map.entry(*key).or_insert(Value::Null);
+                set_path(child, rest, setter);
+            }
+        }
+
+        (Value::Array(items), PathSegment::Wildcard) => {
+            for child in items {
+                set_path(child, rest, setter);
+            }
+        }
+
+        (Value::Array(items), PathSegment::Index(index)) => {
+            set_index(items, *index, rest, setter);
+        }
+
+        // Dotted numbers index arrays too, as in `servers.0.port`.
+        (Value::Array(items), PathSegment::Key(key)) => {
+            if let Ok(index) = key.parse() {
+                set_index(items, index, rest, setter);
+            }
+        }
+
+        // A scalar sits where the path expects a nested value, or the path
+        // indexes an object; leave it alone rather than discarding it.
+        _ => {}
+    }
+}
+
+/// Sets the element at `index`, appending it if the index is right past the
+/// end of the array.
+fn set_index(items: &mut Vec<Value>, index: usize, path: &[PathSegment], setter: &Setter) {
+    if index == items.len() && setter.creates_missing() {
+        items.push(Value::Null);
+    }
+
+    if let Some(child) = items.get_mut(index) {
+        set_path(child, path, setter);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::servers::configs::tests::replace;
+
+    #[test]
+    fn sets_nested_keys_with_types() {
+        let out = apply_json(
+            r#"{"server": {"port": 1, "name": "old"}}"#,
+            &[
+                replace("server.port", "25565"),
+                replace("server.online", "true"),
+                replace("server.name", "new"),
+            ],
+        )
+        .expect("valid JSON");
+
+        let value: Value = serde_json::from_str(&out).expect("valid JSON output");
+        assert_eq!(value["server"]["port"], 25565);
+        assert_eq!(value["server"]["online"], true);
+        assert_eq!(value["server"]["name"], "new");
+    }
+
+    #[test]
+    fn empty_file_starts_from_an_empty_document() {
+        let out = apply_json("", &[replace("a.b", "c")]).expect("valid JSON");
+        let value: Value = serde_json::from_str(&out).expect("valid JSON output");
+
+        assert_eq!(value, serde_json::json!({"a": {"b": "c"}}));
+    }
+
+    #[test]
+    fn indexes_arrays() {
+        let yaml = "listeners:\n- host: 0.0.0.0:25577\n  motd: hi\n";
+        let out =
+            apply_yaml(yaml, &[replace("listeners[0].host", "0.0.0.0:25565")]).expect("valid YAML");
+
+        let value: Value = serde_yaml::from_str(&out).expect("valid YAML output");
+        assert_eq!(value["listeners"][0]["host"], "0.0.0.0:25565");
+        assert_eq!(value["listeners"][0]["motd"], "hi");
+        assert!(value.get("listeners[0]").is_none());
+    }
+
+    #[test]
+    fn creates_missing_array_elements() {
+        let out = apply_json("{}", &[replace("items[0].name", "x")]).expect("valid JSON");
+        let value: Value = serde_json::from_str(&out).expect("valid JSON output");
+
+        assert_eq!(value, serde_json::json!({"items": [{"name": "x"}]}));
+    }
+
+    #[test]
+    fn wildcards_match_every_child() {
+        let out = apply_json(
+            r#"{"servers": {"lobby": {"motd": "a"}, "game": {"motd": "b"}}, "list": [{"x": 1}, {"x": 2}]}"#,
+            &[replace("servers.*.motd", "hello"), replace("list[*].x", "3")],
+        )
+        .expect("valid JSON");
+
+        let value: Value = serde_json::from_str(&out).expect("valid JSON output");
+        assert_eq!(value["servers"]["lobby"]["motd"], "hello");
+        assert_eq!(value["servers"]["game"]["motd"], "hello");
+        assert_eq!(value["list"], serde_json::json!([{"x": 3}, {"x": 3}]));
+    }
+
+    #[test]
+    fn scalars_in_the_way_are_kept() {
+        let out = apply_json(r#"{"a": 1}"#, &[replace("a.b", "2")]).expect("valid JSON");
+        let value: Value = serde_json::from_str(&out).expect("valid JSON output");
+
+        assert_eq!(value, serde_json::json!({"a": 1}));
+    }
+
+    fn replace_if(key: &str, value: &str, condition: &str) -> Replacement {
+        Replacement {
+            condition: Some(condition.to_owned()),
+            ..replace(key, value)
+        }
+    }
+
+    #[test]
+    fn conditions_only_change_matching_values() {
+        let out = apply_json(
+            r#"{"a": {"x": "old", "y": "other"}, "b": {"x": "localhost:25565"}}"#,
+            &[
+                replace_if("a.*", "new", "old"),
+                replace_if("b.x", "172.18.0.1$2", r"regex:^(localhost)(:\d+)?$"),
+            ],
+        )
+        .expect("valid JSON");
+
+        let value: Value = serde_json::from_str(&out).expect("valid JSON output");
+        assert_eq!(
+            value,
+            serde_json::json!({"a": {"x": "new", "y": "other"}, "b": {"x": "172.18.0.1:25565"}})
+        );
+    }
+
+    #[test]
+    fn conditions_never_create_values() {
+        let out = apply_json(
+            r#"{"a": {}}"#,
+            &[
+                replace_if("a.b", "x", ""),
+                replace_if("c.d", "x", "regex:.*"),
+                replace_if("e[0]", "x", ""),
+            ],
+        )
+        .expect("valid JSON");
+
+        let value: Value = serde_json::from_str(&out).expect("valid JSON output");
+        assert_eq!(value, serde_json::json!({"a": {}}));
+    }
+
+    #[test]
+    fn invalid_regex_conditions_are_skipped() {
+        let out =
+            apply_json(r#"{"a": "b"}"#, &[replace_if("a", "c", "regex:(")]).expect("valid JSON");
+
+        let value: Value = serde_json::from_str(&out).expect("valid JSON output");
+        assert_eq!(value, serde_json::json!({"a": "b"}));
+    }
+}
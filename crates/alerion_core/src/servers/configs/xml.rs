@@ -0,0 +1,234 @@
+use xmltree::{Element, EmitterConfig, XMLNode};
+
+use super::{parse_path, ConfigFileError, PathSegment, Replacement};
+
+/// Sets element text following JSONPath-style paths that start at the root
+/// element. `name[i]` picks the i-th child named `name`, a final segment
+/// starting with `@` sets an attribute instead, and `*` matches every child
+/// element at that level. Missing elements are created.
+pub fn apply(contents: &str, replacements: &[Replacement]) -> Result<String, ConfigFileError> {
+    let mut root: Option<Element> = if contents.trim().is_empty() {
+        None
+    } else {
+        Some(Element::parse(contents.as_bytes())?)
+    };
+
+    for replacement in replacements {
+        let path = parse_path(&replacement.key);
+
+        let rest = match path.split_first() {
+            Some((PathSegment::Key(name), rest)) => {
+                let root = root.get_or_insert_with(|| Element::new(name));
+
+                if root.name != *name {
+                    continue;
+                }
+
+                rest
+            }
+            Some((PathSegment::Wildcard, rest)) => rest,
+            _ => continue,
+        };
+
+        if let Some(root) = root.as_mut() {
+            set_path(root, rest, &replacement.value);
+        }
+    }
+
+    let Some(root) = root else {
+        return Ok(String::new());
+    };
+
+    let config = EmitterConfig::new()
+        .perform_indent(true)
+        .write_document_declaration(true);
+
+    let mut out = Vec::new();
+    root.write_with_config(&mut out, config)?;
+    out.push(b'\n');
+
+    Ok(String::from_utf8_lossy(&out).into_owned())
+}
+
+fn set_path(element: &mut Element, path: &[PathSegment], value: &str) {
+    let Some((segment, rest)) = path.split_first() else {
+        element.children.retain(|c| !matches!(c, XMLNode::Text(_)));
+        element.children.push(XMLNode::Text(value.to_owned()));
+        return;
+    };
+
+    match *segment {
+        PathSegment::Key(name) => {
+            if let Some(attribute) = name.strip_prefix('@') {
+                if rest.is_empty() {
+                    element
+                        .attributes
+                        .insert(attribute.to_owned(), value.to_owned());
+                }
+
+                return;
+            }
+
+            match rest.split_first() {
+                Some((PathSegment::Index(index), rest)) => {
+                    set_nth_child(element, Some(name), *index, rest, value);
+                }
+                _ => set_named_children(element, name, rest, value),
+            }
+        }
+
+        PathSegment::Index(index) => set_nth_child(element, None, index, rest, value),
+
+        PathSegment::Wildcard => {
+            for child in child_elements(element, None) {
+                set_path(child, rest, value);
+            }
+        }
+    }
+}
+
+/// Sets every child named `name`, creating one if there is none.
+fn set_named_children(element: &mut Element, name: &str, path: &[PathSegment], value: &str) {
+    let mut matched = false;
+
+    for child in child_elements(element, Some(name)) {
+        matched = true;
+        set_path(child, path, value);
+    }
+
+    if !matched {
+        let mut child = Element::new(name);
+        set_path(&mut child, path, value);
+        element.children.push(XMLNode::Element(child));
+    }
+}
+
+/// Sets the `index`-th child element, among the ones named `name` if given.
+/// A named child right past the last one is created.
+fn set_nth_child(
+    element: &mut Element,
+    name: Option<&str>,
+    index: usize,
+    path: &[PathSegment],
+    value: &str,
+) {
+    let count = child_elements(element, name).count();
+
+    if index < count {
+        if let Some(child) = child_elements(element, name).nth(index) {
+            set_path(child, path, value);
+        }
+    } else if let Some(name) = name.filter(|_| index == count) {
+        let mut child = Element::new(name);
+        set_path(&mut child, path, value);
+        element.children.push(XMLNode::Element(child));
+    }
+}
+
+fn child_elements<'a>(
+    element: &'a mut Element,
+    name: Option<&'a str>,
+) -> impl Iterator<Item = &'a mut Element> {
+    element
+        .children
+        .iter_mut()
+        .filter_map(XMLNode::as_mut_element)
+        .filter(move |child| name.map_or(true, |name| child.name == name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::servers::configs::tests::replace;
+
+    fn parse(xml: &str) -> Element {
+        Element::parse(xml.as_bytes()).expect("valid XML output")
+    }
+
+    fn text(element: &Element, name: &str) -> Option<String> {
+        element
+            .get_child(name)
+            .and_then(|child| child.get_text())
+            .map(|text| text.into_owned())
+    }
+
+    #[test]
+    fn sets_text_and_attributes() {
+        let out = apply(
+            r#"<config><port>1</port><server name="old"/></config>"#,
+            &[
+                replace("config.port", "25565"),
+                replace("config.server.@name", "new"),
+            ],
+        )
+        .expect("valid XML");
+
+        let root = parse(&out);
+        assert_eq!(text(&root, "port").as_deref(), Some("25565"));
+        assert_eq!(
+            root.get_child("server")
+                .and_then(|s| s.attributes.get("name"))
+                .map(String::as_str),
+            Some("new")
+        );
+    }
+
+    #[test]
+    fn creates_missing_elements() {
+        let out = apply("<config/>", &[replace("config.network.port", "80")]).expect("valid XML");
+        let root = parse(&out);
+
+        let network = root.get_child("network").expect("created element");
+        assert_eq!(text(network, "port").as_deref(), Some("80"));
+    }
+
+    #[test]
+    fn empty_file_gets_a_root_element() {
+        let out = apply("", &[replace("config.port", "80")]).expect("valid XML");
+        let root = parse(&out);
+
+        assert_eq!(root.name, "config");
+        assert_eq!(text(&root, "port").as_deref(), Some("80"));
+    }
+
+    #[test]
+    fn other_roots_are_left_alone() {
+        let out = apply("<config/>", &[replace("other.port", "80")]).expect("valid XML");
+
+        assert!(parse(&out).get_child("port").is_none());
+    }
+
+    #[test]
+    fn indexes_pick_one_of_same_named_children() {
+        let out = apply(
+            "<config><host>a</host><host>b</host></config>",
+            &[replace("config.host[1]", "c")],
+        )
+        .expect("valid XML");
+
+        let root = parse(&out);
+        let hosts: Vec<_> = root
+            .children
+            .iter()
+            .filter_map(XMLNode::as_element)
+            .filter_map(|e| e.get_text().map(|t| t.into_owned()))
+            .collect();
+
+        assert_eq!(hosts, ["a", "c"]);
+    }
+
+    #[test]
+    fn wildcards_match_every_child() {
+        let out = apply(
+            "<config><a><port>1</port></a><b><port>2</port></b></config>",
+            &[replace("config.*.port", "3")],
+        )
+        .expect("valid XML");
+
+        let root = parse(&out);
+        for name in ["a", "b"] {
+            let child = root.get_child(name).expect("child element");
+            assert_eq!(text(child, "port").as_deref(), Some("3"));
+        }
+    }
+}
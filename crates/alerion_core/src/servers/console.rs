@@ -0,0 +1,449 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use alerion_datamodel::remote::server::StartupConfig;
+use regex::Regex;
+
+use crate::config::AlerionThrottles;
+
+/// Reassembles lines from the arbitrarily chunked output of an attached
+/// container.
+#[derive(Debug, Default)]
+pub struct LineSplitter {
+    pending: Vec<u8>,
+}
+
+impl LineSplitter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a chunk of raw output and returns every line it completed, with
+    /// the trailing line terminator removed.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(chunk);
+
+        let mut lines = Vec::new();
+
+        while let Some(pos) = self.pending.iter().position(|b| *b == b'\n') {
+            let mut line: Vec<u8> = self.pending.drain(..=pos).collect();
+            line.pop();
+
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+
+            lines.push(String::from_utf8_lossy(&line).into_owned());
+        }
+
+        lines
+    }
+
+    /// Returns whatever output is left after the stream ended without a
+    /// final newline.
+    pub fn finish(self) -> Option<String> {
+        if self.pending.is_empty() {
+            None
+        } else {
+            Some(String::from_utf8_lossy(&self.pending).into_owned())
+        }
+    }
+}
+
+/// The most recent console lines of a server, oldest first. Once full, each
+/// new line evicts the oldest one.
+#[derive(Debug)]
+pub struct ConsoleHistory {
+    lines: VecDeque<String>,
+    capacity: usize,
+}
+
+impl ConsoleHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            lines: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, line: String) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+        }
+
+        self.lines.push_back(line);
+    }
+
+    /// Returns up to `count` of the latest lines, oldest first.
+    pub fn last(&self, count: usize) -> Vec<String> {
+        let skip = self.lines.len().saturating_sub(count);
+        self.lines.iter().skip(skip).cloned().collect()
+    }
+}
+
+/// What to do with a console line, as decided by [`ConsoleThrottle`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Throttled {
+    /// The line is within the limit.
+    No,
+    /// The limit was just hit; this and the following lines of the interval
+    /// are dropped.
+    Activated,
+    /// The limit was hit earlier in the interval and the line is dropped.
+    Dropping,
+    /// The limit was just hit once too many and the server should be stopped.
+    Exceeded,
+}
+
+/// Limits how many console lines a server may print per interval.
+///
+/// Every interval in which the limit is hit counts as an activation, and an
+/// activation is forgiven after a quiet decay interval. Reaching the maximum
+/// number of activations means the server is flooding the console on purpose
+/// or is stuck in a loop.
+#[derive(Debug)]
+pub struct ConsoleThrottle {
+    config: AlerionThrottles,
+    interval_start: Instant,
+    lines: u64,
+    activated: bool,
+    activations: u64,
+    last_activation: Instant,
+}
+
+impl ConsoleThrottle {
+    pub fn new(config: AlerionThrottles) -> Self {
+        let now = Instant::now();
+
+        Self {
+            config,
+            interval_start: now,
+            lines: 0,
+            activated: false,
+            activations: 0,
+            last_activation: now,
+        }
+    }
+
+    /// Forgets all past output, for when the server starts again.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.config.clone());
+    }
+
+    /// Accounts for one more line of output.
+    pub fn check(&mut self) -> Throttled {
+        self.check_at(Instant::now())
+    }
+
+    /// Accounts for one more line of output, printed at `now`.
+    fn check_at(&mut self, now: Instant) -> Throttled {
+        if !self.config.enabled {
+            return Throttled::No;
+        }
+
+        let interval = Duration::from_millis(self.config.line_reset_interval);
+        let decay = Duration::from_millis(self.config.decay_interval);
+
+        if now.duration_since(self.interval_start) >= interval {
+            self.interval_start = now;
+            self.lines = 0;
+            self.activated = false;
+        }
+
+        if self.activations > 0 && now.duration_since(self.last_activation) >= decay {
+            self.activations -= 1;
+            self.last_activation = now;
+        }
+
+        self.lines += 1;
+
+        if self.lines <= self.config.lines {
+            return Throttled::No;
+        }
+
+        if self.activated {
+            return Throttled::Dropping;
+        }
+
+        self.activated = true;
+        self.activations += 1;
+        self.last_activation = now;
+
+        if self.activations >= self.config.maximum_trigger_count {
+            Throttled::Exceeded
+        } else {
+            Throttled::Activated
+        }
+    }
+}
+
+/// Removes ANSI escape sequences (colours, cursor movement, ...) from `line`.
+pub fn strip_ansi(line: &str) -> Cow<'_, str> {
+    static ANSI: OnceLock<Regex> = OnceLock::new();
+
+    let ansi = ANSI.get_or_init(|| {
+        Regex::new(
+            r"[\x1b\x{9b}][\[\]()#;?]*(?:(?:(?:[a-zA-Z\d]*(?:;[a-zA-Z\d]*)*)?\x07)|(?:(?:\d{1,4}(?:;\d{0,4})*)?[\dA-PRZcf-ntqry=><~]))",
+        )
+        .expect("ANSI escape regex should be valid")
+    });
+
+    ansi.replace_all(line, "")
+}
+
+#[derive(Debug)]
+enum DoneMatcher {
+    Literal(String),
+    Regex(Regex),
+}
+
+/// Recognizes the console line an egg prints once the server has finished
+/// starting, as configured in [`StartupConfig::done`].
+///
+/// Markers are plain substrings unless prefixed with `regex:`.
+#[derive(Debug)]
+pub struct StartupDetector {
+    matchers: Vec<DoneMatcher>,
+    strip_ansi: bool,
+}
+
+impl StartupDetector {
+    pub fn from_config(config: &StartupConfig) -> Self {
+        let matchers = config
+            .done
+            .iter()
+            .filter_map(|done| match done.strip_prefix("regex:") {
+                Some(pattern) => match Regex::new(pattern) {
+                    Ok(re) => Some(DoneMatcher::Regex(re)),
+                    Err(e) => {
+                        tracing::warn!("ignoring invalid startup done pattern {pattern:?}: {e}");
+                        None
+                    }
+                },
+                None => Some(DoneMatcher::Literal(done.clone())),
+            })
+            .collect();
+
+        Self {
+            matchers,
+            strip_ansi: config.strip_ansi,
+        }
+    }
+
+    /// Whether no marker is configured, in which case a server counts as
+    /// running as soon as its container is up.
+    pub fn is_empty(&self) -> bool {
+        self.matchers.is_empty()
+    }
+
+    pub fn matches(&self, line: &str) -> bool {
+        let line = if self.strip_ansi {
+            strip_ansi(line)
+        } else {
+            Cow::Borrowed(line)
+        };
+
+        self.matchers.iter().any(|matcher| match matcher {
+            DoneMatcher::Literal(s) => line.contains(s.as_str()),
+            DoneMatcher::Regex(re) => re.is_match(&line),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn throttle() -> (ConsoleThrottle, Instant) {
+        let throttle = ConsoleThrottle::new(AlerionThrottles {
+            enabled: true,
+            lines: 2,
+            line_reset_interval: 100,
+            maximum_trigger_count: 3,
+            decay_interval: 1_000,
+        });
+        let start = throttle.interval_start;
+
+        (throttle, start)
+    }
+
+    /// Prints three lines at `at`, which goes over the limit once.
+    fn flood(throttle: &mut ConsoleThrottle, at: Instant) -> Throttled {
+        assert_eq!(throttle.check_at(at), Throttled::No);
+        assert_eq!(throttle.check_at(at), Throttled::No);
+        throttle.check_at(at)
+    }
+
+    #[test]
+    fn activates_once_per_interval() {
+        let (mut throttle, start) = throttle();
+
+        assert_eq!(flood(&mut throttle, start), Throttled::Activated);
+        assert_eq!(throttle.check_at(start), Throttled::Dropping);
+        assert_eq!(throttle.check_at(start), Throttled::Dropping);
+
+        let next = start + Duration::from_millis(100);
+        assert_eq!(throttle.check_at(next), Throttled::No);
+    }
+
+    #[test]
+    fn exceeds_at_the_maximum_trigger_count() {
+        let (mut throttle, start) = throttle();
+
+        assert_eq!(flood(&mut throttle, start), Throttled::Activated);
+        let second = start + Duration::from_millis(100);
+        assert_eq!(flood(&mut throttle, second), Throttled::Activated);
+        let third = start + Duration::from_millis(200);
+        assert_eq!(flood(&mut throttle, third), Throttled::Exceeded);
+    }
+
+    #[test]
+    fn activations_decay_after_a_quiet_interval() {
+        let (mut throttle, start) = throttle();
+
+        assert_eq!(flood(&mut throttle, start), Throttled::Activated);
+        let second = start + Duration::from_millis(100);
+        assert_eq!(flood(&mut throttle, second), Throttled::Activated);
+
+        // One activation is forgiven, so the next one is only the second.
+        let later = second + Duration::from_millis(1_000);
+        assert_eq!(flood(&mut throttle, later), Throttled::Activated);
+        let last = later + Duration::from_millis(100);
+        assert_eq!(flood(&mut throttle, last), Throttled::Exceeded);
+    }
+
+    #[test]
+    fn disabled_throttle_lets_everything_through() {
+        let (mut throttle, start) = throttle();
+        throttle.config.enabled = false;
+
+        for _ in 0..10 {
+            assert_eq!(throttle.check_at(start), Throttled::No);
+        }
+    }
+
+    fn detector(done: &[&str], strip_ansi: bool) -> StartupDetector {
+        StartupDetector::from_config(&StartupConfig {
+            done: done.iter().map(|d| (*d).to_owned()).collect(),
+            user_interaction: Vec::new(),
+            strip_ansi,
+        })
+    }
+
+    const COLOURED_DONE: &str =
+        "\x1b[32m[12:00:00 INFO]: Done (3.14s)! For help, type \"help\"\x1b[0m";
+
+    #[test]
+    fn literal_markers_match_substrings() {
+        let detector = detector(&[")! For help, type "], false);
+
+        assert!(detector.matches("[12:00:00 INFO]: Done (3.14s)! For help, type \"help\""));
+        assert!(!detector.matches("[12:00:00 INFO]: Preparing spawn area: 84%"));
+    }
+
+    #[test]
+    fn regex_markers() {
+        let detector = detector(&[r"regex:^\[[\d:]+ INFO\]: Done \([\d.]+s\)!"], false);
+
+        assert!(detector.matches("[12:00:00 INFO]: Done (3.14s)! For help, type \"help\""));
+        assert!(!detector.matches("<player> [12:00:00 INFO]: Done (3.14s)!"));
+    }
+
+    #[test]
+    fn invalid_regex_markers_are_skipped() {
+        assert!(detector(&["regex:("], false).is_empty());
+
+        let detector = detector(&["regex:Done (", "Listening on"], false);
+
+        assert!(!detector.is_empty());
+        assert!(detector.matches("Listening on 0.0.0.0:25577"));
+        assert!(!detector.matches("Done (3.14s)!"));
+    }
+
+    #[test]
+    fn strips_ansi_only_when_asked() {
+        let marker = [r"regex:^\[[\d:]+ INFO\]: Done"];
+
+        assert!(detector(&marker, true).matches(COLOURED_DONE));
+        assert!(!detector(&marker, false).matches(COLOURED_DONE));
+    }
+
+    #[test]
+    fn strip_ansi_removes_colours() {
+        assert_eq!(
+            strip_ansi(COLOURED_DONE),
+            "[12:00:00 INFO]: Done (3.14s)! For help, type \"help\""
+        );
+        assert!(matches!(strip_ansi("plain"), Cow::Borrowed("plain")));
+    }
+
+    #[test]
+    fn no_markers_is_empty() {
+        let detector = detector(&[], false);
+
+        assert!(detector.is_empty());
+        assert!(!detector.matches("Done (3.14s)!"));
+    }
+
+    #[test]
+    fn splits_lines_across_chunks() {
+        let mut splitter = LineSplitter::new();
+
+        assert!(splitter.push(b"first li").is_empty());
+        assert_eq!(splitter.push(b"ne\nsecond\nthi"), ["first line", "second"]);
+        assert_eq!(splitter.push(b"rd\n"), ["third"]);
+        assert_eq!(splitter.finish(), None);
+    }
+
+    #[test]
+    fn strips_crlf() {
+        let mut splitter = LineSplitter::new();
+
+        assert_eq!(splitter.push(b"a\r\nb\r"), ["a"]);
+        assert_eq!(splitter.push(b"\n"), ["b"]);
+    }
+
+    #[test]
+    fn finish_returns_the_unterminated_line() {
+        let mut splitter = LineSplitter::new();
+
+        assert_eq!(splitter.push(b"done\n> "), ["done"]);
+        assert_eq!(splitter.finish().as_deref(), Some("> "));
+    }
+
+    #[test]
+    fn history_evicts_the_oldest_lines() {
+        let mut history = ConsoleHistory::new(3);
+
+        for i in 0..5 {
+            history.push(i.to_string());
+        }
+
+        assert_eq!(history.last(3), ["2", "3", "4"]);
+        assert_eq!(history.last(2), ["3", "4"]);
+    }
+
+    #[test]
+    fn history_returns_what_it_has() {
+        let mut history = ConsoleHistory::new(10);
+        history.push("a".to_owned());
+        history.push("b".to_owned());
+
+        assert_eq!(history.last(5), ["a", "b"]);
+        assert!(history.last(0).is_empty());
+    }
+
+    #[test]
+    fn zero_capacity_history_keeps_nothing() {
+        let mut history = ConsoleHistory::new(0);
+        history.push("a".to_owned());
+
+        assert!(history.last(5).is_empty());
+    }
+}
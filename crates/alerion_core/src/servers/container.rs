@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use alerion_datamodel::remote::server::{AllocationConfig, BuildConfig, ServerSettings};
+use bollard::container::Config;
+use bollard::models::{HostConfig, Mount, MountTypeEnum, PortBinding, PortMap};
+
+use crate::config::AlerionUser;
+use crate::templating::{value_to_string, TemplateContext};
+
+/// Where the server volume is mounted inside the container.
+pub const CONTAINER_HOME: &str = "/home/container";
+
+/// Where the server volume is mounted inside the installer container.
+pub const INSTALLER_SERVER_DIR: &str = "/mnt/server";
+
+/// Where the directory holding the installation script is mounted inside the
+/// installer container.
+pub const INSTALLER_SCRIPT_DIR: &str = "/mnt/install";
+
+/// Builds the Docker container spec for a server from its panel settings.
+pub fn container_config(
+    settings: &ServerSettings,
+    template: &TemplateContext,
+    volume_dir: &Path,
+    user: AlerionUser,
+) -> Config<String> {
+    let port_bindings = port_bindings(&settings.allocations);
+
+    let exposed_ports = port_bindings
+        .keys()
+        .map(|port| (port.clone(), HashMap::new()))
+        .collect();
+
+    let host_config = HostConfig {
+        port_bindings: Some(port_bindings),
+        mounts: Some(mounts(settings, volume_dir)),
+        tmpfs: Some(HashMap::from([(
+            "/tmp".to_owned(),
+            "rw,exec,nosuid,size=100M".to_owned(),
+        )])),
+        security_opt: Some(vec!["no-new-privileges".to_owned()]),
+        ..resources(&settings.build)
+    };
+
+    Config {
+        hostname: Some(settings.uuid.as_hyphenated().to_string()),
+        user: Some(format!("{}:{}", user.uid, user.gid)),
+        attach_stdin: Some(true),
+        attach_stdout: Some(true),
+        attach_stderr: Some(true),
+        open_stdin: Some(true),
+        tty: Some(true),
+        exposed_ports: Some(exposed_ports),
+        image: Some(settings.container.image.trim_start_matches('~').to_owned()),
+        env: Some(environment(settings, template)),
+        host_config: Some(host_config),
+        ..Config::default()
+    }
+}
+
+/// Builds the spec of the throwaway container that runs an egg's installation
+/// script against the server volume.
+pub fn installer_config(
+    settings: &ServerSettings,
+    template: &TemplateContext,
+    image: &str,
+    entrypoint: &str,
+    volume_dir: &Path,
+    script_dir: &Path,
+) -> Config<String> {
+    let mounts = vec![
+        bind_mount(volume_dir, INSTALLER_SERVER_DIR),
+        bind_mount(script_dir, INSTALLER_SCRIPT_DIR),
+    ];
+
+    let host_config = HostConfig {
+        mounts: Some(mounts),
+        tmpfs: Some(HashMap::from([(
+            "/tmp".to_owned(),
+            "rw,exec,nosuid,size=100M".to_owned(),
+        )])),
+        ..resources(&settings.build)
+    };
+
+    Config {
+        hostname: Some("installer".to_owned()),
+        attach_stdin: Some(true),
+        attach_stdout: Some(true),
+        attach_stderr: Some(true),
+        open_stdin: Some(true),
+        tty: Some(true),
+        image: Some(image.to_owned()),
+        env: Some(environment(settings, template)),
+        cmd: Some(vec![
+            entrypoint.to_owned(),
+            format!("{INSTALLER_SCRIPT_DIR}/install.sh"),
+        ]),
+        host_config: Some(host_config),
+        ..Config::default()
+    }
+}
+
+/// Environment variables of the container. Images built for the panel run
+/// whatever `STARTUP` holds, so this is also how `invocation` becomes the
+/// startup command.
+///
+/// `server.*`, `env.*` and `config.*` placeholders are rendered, while bare
+/// `{{VARIABLE}}` ones are left for the image's entrypoint to expand.
+pub fn environment(settings: &ServerSettings, template: &TemplateContext) -> Vec<String> {
+    let allocation = &settings.allocations.default;
+
+    let mut env = vec![
+        format!("STARTUP={}", template.render_startup(&settings.invocation)),
+        format!("SERVER_MEMORY={}", settings.build.memory_limit),
+        format!("SERVER_IP={}", allocation.ip),
+        format!("SERVER_PORT={}", allocation.port),
+    ];
+
+    for (key, value) in &settings.environment {
+        env.push(format!(
+            "{key}={}",
+            template.render_startup(&value_to_string(value))
+        ));
+    }
+
+    env
+}
+
+/// Converts the build limits into Docker resource limits. Memory values from
+/// the panel are in megabytes.
+pub fn resources(build: &BuildConfig) -> HostConfig {
+    let memory = bounded_memory_limit(build.memory_limit);
+
+    let memory_swap = if build.swap < 0 {
+        -1
+    } else {
+        build.swap as i64 * 1_000_000 + memory
+    };
+
+    let mut host_config = HostConfig {
+        memory: Some(memory),
+        memory_reservation: Some(build.memory_limit as i64 * 1_000_000),
+        memory_swap: Some(memory_swap),
+        blkio_weight: blkio_weight(build.io_weight),
+        oom_kill_disable: Some(build.oom_disabled),
+        ..HostConfig::default()
+    };
+
+    // Leaving these unset when there is no limit matters: some Java servers
+    // size their thread pools from the quota they see.
+    if build.cpu_limit > 0 {
+        host_config.cpu_quota = Some(build.cpu_limit as i64 * 1_000);
+        host_config.cpu_period = Some(100_000);
+        host_config.cpu_shares = Some(1024);
+    }
+
+    if let Some(threads) = build.threads.as_ref().filter(|t| !t.is_empty()) {
+        host_config.cpuset_cpus = Some(threads.clone());
+    }
+
+    host_config
+}
+
+/// Memory limit in bytes, with some overhead on top of what the panel
+/// assigned so that runtimes sized to exactly that amount are not OOM killed.
+fn bounded_memory_limit(memory_limit: isize) -> i64 {
+    let multiplier = match memory_limit {
+        ..=2048 => 1.15,
+        2049..=4096 => 1.10,
+        _ => 1.05,
+    };
+
+    (memory_limit as f64 * multiplier * 1_000_000.0).round() as i64
+}
+
+/// Docker only accepts block IO weights between 10 and 1000. A weight of 0
+/// means the panel did not set one, so Docker's default applies.
+fn blkio_weight(io_weight: u32) -> Option<u16> {
+    match io_weight {
+        0 => None,
+        weight => Some(weight.clamp(10, 1000) as u16),
+    }
+}
+
+/// Binds every allocated port on both TCP and UDP.
+pub fn port_bindings(allocations: &AllocationConfig) -> PortMap {
+    let mut bindings = PortMap::new();
+
+    for (ip, ports) in &allocations.mappings {
+        for port in ports.iter().filter(|p| **p > 0) {
+            for protocol in ["tcp", "udp"] {
+                let binding = PortBinding {
+                    host_ip: Some(ip.clone()),
+                    host_port: Some(port.to_string()),
+                };
+
+                bindings
+                    .entry(format!("{port}/{protocol}"))
+                    .or_insert_with(|| Some(Vec::new()))
+                    .get_or_insert_with(Vec::new)
+                    .push(binding);
+            }
+        }
+    }
+
+    bindings
+}
+
+fn bind_mount(source: &Path, target: &str) -> Mount {
+    Mount {
+        target: Some(target.to_owned()),
+        source: Some(source.to_string_lossy().into_owned()),
+        typ: Some(MountTypeEnum::BIND),
+        read_only: Some(false),
+        ..Mount::default()
+    }
+}
+
+fn mounts(settings: &ServerSettings, volume_dir: &Path) -> Vec<Mount> {
+    let volume = bind_mount(volume_dir, CONTAINER_HOME);
+
+    let extra = settings.mounts.iter().map(|m| Mount {
+        target: Some(m.target.clone()),
+        source: Some(m.source.clone()),
+        typ: Some(MountTypeEnum::BIND),
+        read_only: Some(m.read_only),
+        ..Mount::default()
+    });
+
+    std::iter::once(volume).chain(extra).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::servers::test_support::{config, settings};
+
+    #[test]
+    fn environment_renders_namespaced_placeholders() {
+        let mut settings = settings();
+        settings.invocation =
+            "java -Xmx{{SERVER_MEMORY}}M -jar {{SERVER_JARFILE}} --port {{server.build.default.port}}"
+                .to_owned();
+        settings
+            .environment
+            .insert("HOST".to_owned(), json!("{{config.docker.interface}}"));
+        settings
+            .environment
+            .insert("GREETING".to_owned(), json!("{{env.MAX_PLAYERS}} players"));
+
+        let config = config();
+        let env = environment(&settings, &TemplateContext::new(&settings, &config));
+
+        assert!(env.contains(
+            &"STARTUP=java -Xmx{{SERVER_MEMORY}}M -jar {{SERVER_JARFILE}} --port 25565".to_owned()
+        ));
+        assert!(env.contains(&"HOST=172.18.0.1".to_owned()));
+        assert!(env.contains(&"GREETING=20 players".to_owned()));
+    }
+
+    #[test]
+    fn environment_keeps_what_it_cannot_render() {
+        let mut settings = settings();
+        settings
+            .environment
+            .insert("NAME".to_owned(), json!("a {{ b"));
+
+        let config = config();
+        let env = environment(&settings, &TemplateContext::new(&settings, &config));
+
+        // Credentials are not exposed, so the placeholder stays as written.
+        assert!(env.contains(&"MOTD={{config.auth.token}}".to_owned()));
+        assert!(env.contains(&"NAME=a {{ b".to_owned()));
+    }
+
+    #[test]
+    fn resources_use_megabytes_and_valid_io_weights() {
+        let mut build = BuildConfig {
+            memory_limit: 1024,
+            swap: 512,
+            io_weight: 5000,
+            cpu_limit: 0,
+            threads: None,
+            disk_space: 2048,
+            oom_disabled: false,
+        };
+
+        let host_config = resources(&build);
+
+        assert_eq!(host_config.memory, Some(1_177_600_000));
+        assert_eq!(host_config.memory_reservation, Some(1_024_000_000));
+        assert_eq!(host_config.memory_swap, Some(1_689_600_000));
+        assert_eq!(host_config.blkio_weight, Some(1000));
+
+        build.io_weight = 1;
+        assert_eq!(resources(&build).blkio_weight, Some(10));
+
+        build.io_weight = 0;
+        assert_eq!(resources(&build).blkio_weight, None);
+    }
+}
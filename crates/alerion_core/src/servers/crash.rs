@@ -0,0 +1,119 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use alerion_datamodel::websocket::PowerAction;
+use bollard::container::WaitContainerOptions;
+use bollard::errors::Error as DockerError;
+use futures::StreamExt;
+
+use super::{Server, ServerError};
+
+impl Server {
+    /// Called when the server's process exited while it was running. Restarts
+    /// it, unless crash detection is disabled, the exit was clean and clean
+    /// exits are tolerated, or it already crashed within the configured
+    /// timeout.
+    pub(super) async fn handle_crash(self: &Arc<Self>) {
+        let cfg = &self.config.crash_detection;
+
+        if !cfg.enabled {
+            tracing::debug!(
+                "server {} exited, but crash detection is disabled",
+                self.uuid.as_hyphenated()
+            );
+
+            return;
+        }
+
+        let (exit_code, oom_killed) = match self.exit_state().await {
+            Ok(state) => state,
+            Err(e) => {
+                tracing::error!("failed to inspect crashed server {}: {e}", self.uuid);
+                return;
+            }
+        };
+
+        if exit_code == 0 && !oom_killed && !cfg.detect_clean_exit_as_crash {
+            tracing::debug!(
+                "server {} exited cleanly, not treating it as a crash",
+                self.uuid.as_hyphenated()
+            );
+
+            return;
+        }
+
+        tracing::warn!(
+            "server {} crashed (exit code {exit_code}, out of memory: {oom_killed})",
+            self.uuid.as_hyphenated()
+        );
+
+        self.send_daemon_message(
+            "---------- Detected server process in a crashed state! ----------",
+        )
+        .await;
+        self.send_daemon_message(format!("Exit code: {exit_code}"))
+            .await;
+        self.send_daemon_message(format!("Out of memory: {oom_killed}"))
+            .await;
+
+        let timeout = Duration::from_secs(cfg.timeout);
+        let mut last_crash = self.last_crash.lock().await;
+
+        if last_crash.is_some_and(|last| last.elapsed() < timeout) {
+            drop(last_crash);
+
+            self.send_daemon_message(format!(
+                "Aborting automatic restart, last crash occurred less than {} seconds ago.",
+                timeout.as_secs()
+            ))
+            .await;
+
+            return;
+        }
+
+        *last_crash = Some(Instant::now());
+        drop(last_crash);
+
+        if let Err(e) = self.power(PowerAction::Start) {
+            tracing::error!(
+                "failed to restart server {} after crash: {e}",
+                self.uuid.as_hyphenated()
+            );
+        }
+    }
+
+    /// Exit code of the container's last run, and whether it was killed for
+    /// running out of memory.
+    ///
+    /// The attach stream can close before Docker has recorded the exit, so
+    /// this waits for the container to actually stop before inspecting it.
+    async fn exit_state(&self) -> Result<(i64, bool), ServerError> {
+        let opts = WaitContainerOptions {
+            condition: "not-running",
+        };
+
+        let exit_code = match self
+            .docker
+            .wait_container(&self.container_name, Some(opts))
+            .next()
+            .await
+        {
+            Some(Ok(response)) => response.status_code,
+            Some(Err(DockerError::DockerContainerWaitError { code, .. })) => code,
+            Some(Err(e)) => return Err(e.into()),
+            None => 0,
+        };
+
+        let response = self
+            .docker
+            .inspect_container(&self.container_name, None)
+            .await?;
+
+        let oom_killed = response
+            .state
+            .and_then(|state| state.oom_killed)
+            .unwrap_or_default();
+
+        Ok((exit_code, oom_killed))
+    }
+}
@@ -0,0 +1,93 @@
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::time::SystemTime;
+
+use alerion_datamodel::websocket::ServerStatus;
+
+use super::{Server, ServerError};
+
+impl Server {
+    /// Tears down everything the daemon holds for the server: websocket
+    /// sessions, a running installation, the container and the files on
+    /// disk. The data volume is moved under `trash` when given, and erased
+    /// otherwise.
+    ///
+    /// Everything that is already gone is skipped, so a failed deletion can
+    /// simply be attempted again.
+    ///
+    /// No power action or installation can start once this is called. The
+    /// container is killed first, so a stop or restart in progress finishes
+    /// right away instead of holding up the deletion, and is then waited for
+    /// so nothing recreates the container behind the deletion's back.
+    pub(super) async fn delete(&self, trash: Option<&Path>) -> Result<(), ServerError> {
+        let uuid = self.uuid.as_hyphenated();
+
+        tracing::info!("Deleting server {uuid}");
+
+        self.deleting.store(true, Ordering::SeqCst);
+
+        // Dropping the senders ends every session with a "server removed"
+        // close frame.
+        self.websocket_connections.lock().await.clear();
+
+        if let Some(installation) = self.installation.lock().await.take() {
+            installation.abort();
+        }
+
+        // Like a kill power action, this does not wait for the lock. The stop
+        // in progress, if any, sees the container exit and returns.
+        self.kill().await?;
+
+        // Waits for the power action in progress, if any. The aborted
+        // installation releases the lock as soon as its task is dropped.
+        let _power = self.power_lock.lock().await;
+
+        self.remove_installer_container().await?;
+
+        // Keeps crash detection from restarting the server once its
+        // container goes away.
+        self.status.send_replace(ServerStatus::Stopping);
+
+        if let Some(sampler) = self.stats_sampler.lock().await.take() {
+            sampler.abort();
+        }
+
+        if let Some(sampler) = self.disk_usage_sampler.lock().await.take() {
+            sampler.abort();
+        }
+
+        self.remove_docker_container().await?;
+
+        self.status.send_replace(ServerStatus::Offline);
+        *self.stdin.lock().await = None;
+        *self.console_log.lock().await = None;
+
+        ignore_not_found(tokio::fs::remove_dir_all(&self.install_dir).await)?;
+        ignore_not_found(tokio::fs::remove_file(&self.console_log_path).await)?;
+
+        match trash {
+            Some(trash) => {
+                let deleted_at = SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+
+                tokio::fs::create_dir_all(trash).await?;
+
+                let target = trash.join(format!("{uuid}-{deleted_at}"));
+                ignore_not_found(tokio::fs::rename(&self.volume_dir, &target).await)?;
+            }
+            None => ignore_not_found(tokio::fs::remove_dir_all(&self.volume_dir).await)?,
+        }
+
+        Ok(())
+    }
+}
+
+fn ignore_not_found(result: std::io::Result<()>) -> std::io::Result<()> {
+    match result {
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
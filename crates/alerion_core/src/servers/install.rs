@@ -0,0 +1,260 @@
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use alerion_datamodel::remote::server::GetServerInstallByUuidResponse;
+use alerion_datamodel::websocket::{PowerAction, ServerStatus, WebsocketEvent};
+use bollard::container::{
+    AttachContainerOptions, CreateContainerOptions, LogOutput, RemoveContainerOptions, StartContainerOptions, WaitContainerOptions
+};
+use bollard::errors::Error as DockerError;
+use futures::StreamExt;
+use tokio::sync::OwnedMutexGuard;
+
+use super::console::LineSplitter;
+use super::{container, Server, ServerError};
+use crate::filesystem;
+
+impl Server {
+    /// Runs the egg's installation script in the background and reports the
+    /// outcome to the panel, optionally starting the server afterwards.
+    ///
+    /// Power actions are refused for as long as the installation runs.
+    pub async fn install(
+        self: &Arc<Self>,
+        reinstall: bool,
+        start_on_completion: bool,
+    ) -> Result<(), ServerError> {
+        let mut installation = self.installation.lock().await;
+
+        if self.deleting.load(Ordering::SeqCst) {
+            return Err(ServerError::Deleting);
+        }
+
+        if installation
+            .as_ref()
+            .is_some_and(|task| !task.is_finished())
+        {
+            return Err(ServerError::InstallInProgress);
+        }
+
+        if self.status() != ServerStatus::Offline {
+            return Err(ServerError::AlreadyRunning);
+        }
+
+        let guard = Arc::clone(&self.power_lock)
+            .try_lock_owned()
+            .map_err(|_| ServerError::PowerActionInProgress)?;
+
+        let server = Arc::clone(self);
+
+        *installation = Some(tokio::spawn(async move {
+            server
+                .run_installation(guard, reinstall, start_on_completion)
+                .await;
+        }));
+
+        Ok(())
+    }
+
+    pub async fn is_installing(&self) -> bool {
+        self.installation
+            .lock()
+            .await
+            .as_ref()
+            .is_some_and(|task| !task.is_finished())
+    }
+
+    async fn run_installation(
+        self: &Arc<Self>,
+        guard: OwnedMutexGuard<()>,
+        reinstall: bool,
+        start_on_completion: bool,
+    ) {
+        let uuid = self.uuid.as_hyphenated();
+
+        tracing::info!("Installing server {uuid}");
+
+        self.send_to_websockets(WebsocketEvent::InstallStarted)
+            .await;
+
+        let successful = if self.info().settings.skip_egg_scripts {
+            tracing::info!("Skipping egg installation script for server {uuid}");
+            true
+        } else {
+            match self.run_installation_script().await {
+                Ok(successful) => successful,
+                Err(e) => {
+                    tracing::error!("failed to install server {uuid}: {e}");
+                    false
+                }
+            }
+        };
+
+        if let Err(e) = self
+            .remote_api
+            .post_installation_status(self.uuid, successful, reinstall)
+            .await
+        {
+            tracing::error!("failed to report installation status of server {uuid}: {e}");
+        }
+
+        self.send_to_websockets(WebsocketEvent::InstallCompleted)
+            .await;
+
+        drop(guard);
+
+        if successful && start_on_completion {
+            if let Err(e) = self.power(PowerAction::Start) {
+                tracing::error!("failed to start server {uuid} after installation: {e}");
+            }
+        }
+    }
+
+    /// Runs the installation script to completion and returns whether it
+    /// exited successfully.
+    async fn run_installation_script(&self) -> Result<bool, ServerError> {
+        let instructions = self.remote_api.get_install_instructions(self.uuid).await?;
+
+        tokio::fs::create_dir_all(&self.volume_dir).await?;
+        tokio::fs::create_dir_all(&self.install_dir).await?;
+
+        let result = self.run_installer(&instructions).await;
+
+        // Runs whether or not the installer succeeded, so a failed attach or
+        // start does not leave the container and script behind.
+        let cleanup = self.remove_installer_files().await;
+
+        let successful = result?;
+        cleanup?;
+
+        // The script runs as root, so whatever it created has to be handed
+        // over to the user the server runs as.
+        filesystem::chown_recursive(&self.volume_dir, self.config.user).await?;
+
+        Ok(successful)
+    }
+
+    async fn run_installer(
+        &self,
+        instructions: &GetServerInstallByUuidResponse,
+    ) -> Result<bool, ServerError> {
+        let script = instructions.script.replace("\r\n", "\n");
+        tokio::fs::write(self.install_dir.join("install.sh"), script).await?;
+
+        self.pull_image(&instructions.container_image).await;
+
+        let name = self.installer_container_name();
+        self.remove_installer_container().await?;
+
+        let info = self.info();
+        let template = self.template_context(&info.settings);
+        let config = container::installer_config(
+            &info.settings,
+            &template,
+            &instructions.container_image,
+            &instructions.entrypoint,
+            &self.volume_dir,
+            &self.install_dir,
+        );
+
+        let opts = CreateContainerOptions {
+            name: name.clone(),
+            platform: None,
+        };
+
+        self.docker.create_container(Some(opts), config).await?;
+
+        let attach_opts = AttachContainerOptions::<String> {
+            stdout: Some(true),
+            stderr: Some(true),
+            stream: Some(true),
+            ..AttachContainerOptions::default()
+        };
+
+        let mut output = self
+            .docker
+            .attach_container(&name, Some(attach_opts))
+            .await?
+            .output;
+
+        self.docker
+            .start_container(&name, None::<StartContainerOptions<String>>)
+            .await?;
+
+        let mut splitter = LineSplitter::new();
+
+        while let Some(Ok(chunk)) = output.next().await {
+            if let LogOutput::StdIn { .. } = chunk {
+                continue;
+            }
+
+            for line in splitter.push(chunk.as_ref()) {
+                self.send_install_output(line).await;
+            }
+        }
+
+        if let Some(line) = splitter.finish() {
+            self.send_install_output(line).await;
+        }
+
+        let exit = self
+            .docker
+            .wait_container(&name, None::<WaitContainerOptions<String>>)
+            .next()
+            .await;
+
+        let successful = match exit {
+            Some(Ok(response)) => response.status_code == 0,
+            Some(Err(DockerError::DockerContainerWaitError { code, .. })) => {
+                tracing::info!(
+                    "installation script of server {} exited with code {code}",
+                    self.uuid.as_hyphenated()
+                );
+                false
+            }
+            Some(Err(e)) => return Err(e.into()),
+            None => false,
+        };
+
+        Ok(successful)
+    }
+
+    /// Removes the installer container and the directory holding the script.
+    async fn remove_installer_files(&self) -> Result<(), ServerError> {
+        self.remove_installer_container().await?;
+
+        match tokio::fs::remove_dir_all(&self.install_dir).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn installer_container_name(&self) -> String {
+        format!("{}_installer", self.uuid.as_hyphenated())
+    }
+
+    /// Removes the installer container, if there is one.
+    pub(super) async fn remove_installer_container(&self) -> Result<(), ServerError> {
+        let opts = RemoveContainerOptions {
+            force: true,
+            ..RemoveContainerOptions::default()
+        };
+
+        match self
+            .docker
+            .remove_container(&self.installer_container_name(), Some(opts))
+            .await
+        {
+            Ok(())
+            | Err(DockerError::DockerResponseServerError {
+                status_code: 404, ..
+            }) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn send_install_output(&self, line: String) {
+        self.send_to_websockets(WebsocketEvent::InstallOutput(line))
+            .await;
+    }
+}
@@ -0,0 +1,257 @@
+use std::io::{ErrorKind, SeekFrom};
+use std::path::Path;
+
+use bollard::container::LogsOptions;
+use bollard::errors::Error as DockerError;
+use futures::StreamExt;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use super::console::LineSplitter;
+use super::{Server, ServerError};
+
+/// Size past which the daemon's console log of a server is cut down.
+const CONSOLE_LOG_MAX_BYTES: u64 = 4 * 1024 * 1024;
+
+/// How much of the latest output is kept when the console log is cut down.
+const CONSOLE_LOG_KEEP_BYTES: u64 = 1024 * 1024;
+
+/// How much of a file is read at once when looking for its last lines.
+const TAIL_CHUNK_BYTES: u64 = 16 * 1024;
+
+/// The daemon's console log of a server, open for appending.
+pub struct ConsoleLog {
+    file: File,
+    len: u64,
+}
+
+impl Server {
+    /// Up to `count` of the latest console lines, oldest first. They come
+    /// from Docker while the container exists, and from the daemon's own
+    /// console log once it is gone.
+    pub async fn logs(&self, count: usize) -> Result<Vec<String>, ServerError> {
+        match self.docker_log_tail(count).await {
+            Ok(lines) => Ok(lines),
+            Err(ServerError::Docker(DockerError::DockerResponseServerError {
+                status_code: 404,
+                ..
+            })) => match tail_lines(&self.console_log_path, count).await {
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+                result => Ok(result?),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The last `count` lines the container wrote to stdout and stderr.
+    pub(super) async fn docker_log_tail(&self, count: usize) -> Result<Vec<String>, ServerError> {
+        let opts = LogsOptions::<String> {
+            stdout: true,
+            stderr: true,
+            tail: count.to_string(),
+            ..LogsOptions::default()
+        };
+
+        let mut logs = self.docker.logs(&self.container_name, Some(opts));
+        let mut splitter = LineSplitter::new();
+        let mut lines = Vec::new();
+
+        while let Some(chunk) = logs.next().await {
+            lines.extend(splitter.push(chunk?.as_ref()));
+        }
+
+        lines.extend(splitter.finish());
+
+        Ok(lines)
+    }
+
+    /// Appends `line` to the daemon's console log of the server. Once the
+    /// log grows past [`CONSOLE_LOG_MAX_BYTES`], only its latest output is
+    /// kept, so servers running for weeks do not fill the disk.
+    pub(super) async fn append_console_log(&self, line: &str) {
+        let mut log = self.console_log.lock().await;
+
+        if log.is_none() {
+            match self.open_console_log(false).await {
+                Ok(opened) => *log = Some(opened),
+                Err(e) => {
+                    tracing::debug!("failed to open console log of server {}: {e}", self.uuid);
+                    return;
+                }
+            }
+        }
+
+        let Some(current) = log.as_mut() else {
+            return;
+        };
+
+        let written = async {
+            current.file.write_all(line.as_bytes()).await?;
+            current.file.write_all(b"\n").await
+        };
+
+        if let Err(e) = written.await {
+            tracing::debug!("failed to write console log of server {}: {e}", self.uuid);
+            *log = None;
+            return;
+        }
+
+        current.len += line.len() as u64 + 1;
+
+        if current.len > CONSOLE_LOG_MAX_BYTES {
+            *log = None;
+
+            match self.compact_console_log().await {
+                Ok(compacted) => *log = Some(compacted),
+                Err(e) => {
+                    tracing::debug!(
+                        "failed to cut down console log of server {}: {e}",
+                        self.uuid
+                    );
+                }
+            }
+        }
+    }
+
+    /// Empties the daemon's console log, so it only covers the latest run of
+    /// the server.
+    pub(super) async fn truncate_console_log(&self) {
+        let mut log = self.console_log.lock().await;
+
+        match self.open_console_log(true).await {
+            Ok(opened) => *log = Some(opened),
+            Err(e) => {
+                tracing::debug!(
+                    "failed to truncate console log of server {}: {e}",
+                    self.uuid
+                );
+                *log = None;
+            }
+        }
+    }
+
+    /// Rewrites the console log with only its last
+    /// [`CONSOLE_LOG_KEEP_BYTES`], starting at a line boundary.
+    async fn compact_console_log(&self) -> std::io::Result<ConsoleLog> {
+        let mut file = File::open(&self.console_log_path).await?;
+        let len = file.metadata().await?.len();
+
+        let start = len.saturating_sub(CONSOLE_LOG_KEEP_BYTES);
+        let mut kept = Vec::new();
+        file.seek(SeekFrom::Start(start)).await?;
+        file.read_to_end(&mut kept).await?;
+
+        if start > 0 {
+            let first_line = kept.iter().position(|b| *b == b'\n').map_or(0, |i| i + 1);
+            kept.drain(..first_line);
+        }
+
+        let mut log = self.open_console_log(true).await?;
+        log.file.write_all(&kept).await?;
+        log.len = kept.len() as u64;
+
+        Ok(log)
+    }
+
+    async fn open_console_log(&self, truncate: bool) -> std::io::Result<ConsoleLog> {
+        if let Some(parent) = self.console_log_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(!truncate)
+            .truncate(truncate)
+            .open(&self.console_log_path)
+            .await?;
+
+        let len = file.metadata().await?.len();
+
+        Ok(ConsoleLog { file, len })
+    }
+}
+
+/// Reads the last `count` lines of the file at `path`, working backwards
+/// from its end so only as much of it as needed is loaded.
+async fn tail_lines(path: &Path, count: usize) -> std::io::Result<Vec<String>> {
+    let mut file = File::open(path).await?;
+    let len = file.metadata().await?.len();
+
+    let mut pos = len;
+    let mut tail: Vec<u8> = Vec::new();
+    let mut newlines = 0;
+
+    // One more newline than lines wanted marks where the first of them
+    // starts; the file's own trailing newline does not count.
+    while pos > 0 {
+        let trailing = usize::from(tail.last() == Some(&b'\n'));
+
+        if newlines > count + trailing {
+            break;
+        }
+
+        let chunk = pos.min(TAIL_CHUNK_BYTES);
+        pos -= chunk;
+
+        let mut buf = vec![0; chunk as usize];
+        file.seek(SeekFrom::Start(pos)).await?;
+        file.read_exact(&mut buf).await?;
+
+        newlines += buf.iter().filter(|b| **b == b'\n').count();
+        buf.extend_from_slice(&tail);
+        tail = buf;
+    }
+
+    let mut splitter = LineSplitter::new();
+    let mut lines = splitter.push(&tail);
+    lines.extend(splitter.finish());
+
+    // The first line may have been cut in the middle.
+    let skip = lines.len().saturating_sub(count);
+
+    Ok(lines.split_off(skip))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tail_of(contents: &str, count: usize) -> Vec<String> {
+        let path = std::env::temp_dir().join(format!(
+            "alerion-tail-{}-{count}-{}.log",
+            std::process::id(),
+            contents.len()
+        ));
+        std::fs::write(&path, contents).expect("writable temp dir");
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .expect("runtime");
+        let lines = runtime.block_on(tail_lines(&path, count));
+
+        std::fs::remove_file(&path).expect("removable temp file");
+        lines.expect("readable file")
+    }
+
+    #[test]
+    fn tail_of_short_files() {
+        assert_eq!(tail_of("a\nb\nc\n", 2), ["b", "c"]);
+        assert_eq!(tail_of("a\nb\nc", 2), ["b", "c"]);
+        assert_eq!(tail_of("a\nb\n", 5), ["a", "b"]);
+        assert!(tail_of("", 5).is_empty());
+    }
+
+    #[test]
+    fn tail_spans_several_chunks() {
+        let contents = (0..10_000)
+            .map(|i| format!("line {i}\n"))
+            .collect::<Vec<_>>()
+            .concat();
+        let lines = tail_of(&contents, 3000);
+
+        assert_eq!(lines.len(), 3000);
+        assert_eq!(lines[0], "line 7000");
+        assert_eq!(lines[2999], "line 9999");
+    }
+}
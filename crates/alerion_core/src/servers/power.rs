@@ -0,0 +1,261 @@
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use alerion_datamodel::remote::server::StopSignalType;
+use alerion_datamodel::websocket::{PowerAction, ServerStatus};
+use bollard::container::{KillContainerOptions, StartContainerOptions, StopContainerOptions};
+use bollard::errors::Error as DockerError;
+use tokio::task::JoinHandle;
+
+use super::{Server, ServerError};
+
+/// How long a kill waits for the output of the killed container to end.
+const KILL_DETACH_TIMEOUT: Duration = Duration::from_secs(30);
+
+impl Server {
+    /// Runs `action` against this server in the background.
+    ///
+    /// Only one power action may be processed at a time; this fails right away
+    /// with [`ServerError::PowerActionInProgress`] if another one is still
+    /// running. Kills skip that check so a stuck stop can always be cut short.
+    /// Suspended servers can be stopped but not started, which fails with
+    /// [`ServerError::Suspended`]. Servers being deleted refuse every action
+    /// with [`ServerError::Deleting`].
+    pub fn power(
+        self: &Arc<Self>,
+        action: PowerAction,
+    ) -> Result<JoinHandle<Result<(), ServerError>>, ServerError> {
+        if self.deleting.load(Ordering::SeqCst) {
+            return Err(ServerError::Deleting);
+        }
+
+        let starts = matches!(action, PowerAction::Start | PowerAction::Restart);
+
+        if starts && self.server_info.borrow().settings.suspended {
+            return Err(ServerError::Suspended);
+        }
+
+        let guard = match action {
+            PowerAction::Kill => None,
+            _ => Some(
+                Arc::clone(&self.power_lock)
+                    .try_lock_owned()
+                    .map_err(|_| ServerError::PowerActionInProgress)?,
+            ),
+        };
+
+        let server = Arc::clone(self);
+
+        Ok(tokio::spawn(async move {
+            let result = match action {
+                PowerAction::Start => server.start().await,
+                PowerAction::Stop => server.stop().await,
+                PowerAction::Restart => server.restart().await,
+                PowerAction::Kill => server.kill().await,
+            };
+
+            drop(guard);
+
+            if let Err(e) = &result {
+                tracing::error!(
+                    "power action {action:?} failed for server {}: {e}",
+                    server.uuid.as_hyphenated()
+                );
+            }
+
+            result
+        }))
+    }
+
+    async fn start(self: &Arc<Self>) -> Result<(), ServerError> {
+        // A restart whose stop was cut short by a deletion must not bring
+        // the server back.
+        if self.deleting.load(Ordering::SeqCst) {
+            return Err(ServerError::Deleting);
+        }
+
+        if self.status() != ServerStatus::Offline {
+            return Err(ServerError::AlreadyRunning);
+        }
+
+        self.console_throttle.lock().await.reset();
+        self.truncate_console_log().await;
+
+        self.set_status(ServerStatus::Starting).await;
+
+        if let Err(e) = self.start_container().await {
+            self.set_status(ServerStatus::Offline).await;
+            return Err(e);
+        }
+
+        // Otherwise the console pipeline flips the status once the egg's
+        // done marker shows up.
+        if self.server_info.borrow().startup.is_empty() {
+            self.set_status_from(ServerStatus::Starting, ServerStatus::Running)
+                .await;
+        }
+
+        Ok(())
+    }
+
+    async fn start_container(self: &Arc<Self>) -> Result<(), ServerError> {
+        self.update_config_files().await;
+
+        if self.rebuild_required.load(Ordering::SeqCst) {
+            tracing::info!(
+                "Rebuilding docker container of server {}",
+                self.uuid.as_hyphenated()
+            );
+
+            self.remove_docker_container().await?;
+            self.rebuild_required.store(false, Ordering::SeqCst);
+        }
+
+        self.ensure_docker_container().await?;
+        self.attach().await?;
+
+        self.docker
+            .start_container(&self.container_name, None::<StartContainerOptions<String>>)
+            .await?;
+
+        self.spawn_stats_sampler().await;
+
+        Ok(())
+    }
+
+    async fn stop(&self) -> Result<(), ServerError> {
+        if self.status() == ServerStatus::Offline {
+            return Ok(());
+        }
+
+        self.set_status(ServerStatus::Stopping).await;
+
+        let mut status = self.status.subscribe();
+        let timeout = Duration::from_secs(self.config.stop_timeout);
+
+        self.send_stop_signal().await?;
+
+        let stopped =
+            tokio::time::timeout(timeout, status.wait_for(|s| *s == ServerStatus::Offline))
+                .await
+                .is_ok();
+
+        if !stopped {
+            tracing::warn!(
+                "server {} did not stop within {}s, killing it",
+                self.uuid.as_hyphenated(),
+                timeout.as_secs()
+            );
+
+            self.kill().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Asks the server process to shut down the way its egg's stop
+    /// configuration describes.
+    async fn send_stop_signal(&self) -> Result<(), ServerError> {
+        let info = self.info();
+        let stop = &info.process.stop;
+        let value = stop.value.as_deref().unwrap_or_default();
+
+        match stop.kind {
+            StopSignalType::Command if self.stdin.lock().await.is_some() => {
+                self.send_command(value).await
+            }
+
+            // The panel turns `^SIGQUIT` and the like into the bare signal
+            // name, which Docker understands as is.
+            StopSignalType::Signal => {
+                let signal = match value.trim() {
+                    "" => "SIGKILL",
+                    signal => signal,
+                };
+
+                self.kill_container(signal).await
+            }
+
+            // Either the egg asks for a native stop, or we are not attached
+            // and cannot write the stop command.
+            _ => {
+                let docker = Arc::clone(&self.docker);
+                let name = self.container_name.clone();
+                let opts = StopContainerOptions {
+                    t: self.config.stop_timeout as i64,
+                };
+
+                // Docker only answers once the container is gone, which can
+                // take longer than the client's request timeout. `stop` notices
+                // the exit through the status channel instead.
+                tokio::spawn(async move {
+                    if let Err(e) = docker.stop_container(&name, Some(opts)).await {
+                        tracing::debug!("stop request for container {name} failed: {e}");
+                    }
+                });
+
+                Ok(())
+            }
+        }
+    }
+
+    async fn restart(self: &Arc<Self>) -> Result<(), ServerError> {
+        self.stop().await?;
+        self.start().await
+    }
+
+    pub(super) async fn kill(&self) -> Result<(), ServerError> {
+        if self.status() == ServerStatus::Offline {
+            return Ok(());
+        }
+
+        // Keeps crash detection from mistaking the exit for a crash.
+        self.set_status(ServerStatus::Stopping).await;
+
+        let mut status = self.status.subscribe();
+
+        self.kill_container("SIGKILL").await?;
+
+        // The attach task marks the server offline once it has let go of the
+        // container's stdio. Returning earlier would let a following start
+        // attach again while that task is still around to clear the new
+        // stdin and overwrite the new status.
+        let detached = tokio::time::timeout(
+            KILL_DETACH_TIMEOUT,
+            status.wait_for(|s| *s == ServerStatus::Offline),
+        )
+        .await
+        .is_ok();
+
+        if !detached {
+            tracing::warn!(
+                "still attached to server {} {}s after killing it",
+                self.uuid.as_hyphenated(),
+                KILL_DETACH_TIMEOUT.as_secs()
+            );
+
+            self.set_status(ServerStatus::Offline).await;
+        }
+
+        Ok(())
+    }
+
+    async fn kill_container(&self, signal: &str) -> Result<(), ServerError> {
+        let opts = KillContainerOptions { signal };
+
+        match self
+            .docker
+            .kill_container(&self.container_name, Some(opts))
+            .await
+        {
+            // The container is already stopped or gone, which is what we wanted.
+            Ok(())
+            | Err(DockerError::DockerResponseServerError {
+                status_code: 404 | 409,
+                ..
+            }) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
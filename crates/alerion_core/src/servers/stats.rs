@@ -0,0 +1,213 @@
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use alerion_datamodel::websocket::{NetworkStatistics, PerformanceStatisics, WebsocketEvent};
+use bollard::container::{CPUStats, MemoryStatsStats, Stats, StatsOptions};
+use futures::StreamExt;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use super::Server;
+
+/// How often the size of the server volume is recalculated. Walking a large
+/// volume is expensive, so this is much slower than the stats stream.
+const DISK_USAGE_INTERVAL: Duration = Duration::from_secs(60);
+
+impl Server {
+    /// The latest resource usage sample, along with the current state.
+    pub async fn stats(&self) -> PerformanceStatisics {
+        PerformanceStatisics {
+            state: self.status(),
+            disk_bytes: self.disk_bytes.load(Ordering::Relaxed) as usize,
+            ..self.stats.lock().await.clone()
+        }
+    }
+
+    /// Sends the latest sample to every websocket session.
+    pub async fn broadcast_stats(&self) {
+        let stats = self.stats().await;
+        self.send_to_websockets(WebsocketEvent::Stats(stats)).await;
+    }
+
+    /// Starts following the container's Docker stats, replacing any sampler
+    /// that is still running. Docker emits a sample about once per second,
+    /// each of which is broadcast as a `stats` event. The sampler stops by
+    /// itself once the container exits.
+    pub(super) async fn spawn_stats_sampler(self: &Arc<Self>) {
+        let server = Arc::clone(self);
+        let handle = tokio::spawn(async move { server.sample_stats().await });
+
+        if let Some(previous) = self.stats_sampler.lock().await.replace(handle) {
+            previous.abort();
+        }
+    }
+
+    async fn sample_stats(&self) {
+        let started_at = self.container_started_at().await;
+
+        let opts = StatsOptions {
+            stream: true,
+            one_shot: false,
+        };
+
+        let mut samples = self.docker.stats(&self.container_name, Some(opts));
+
+        while let Some(sample) = samples.next().await {
+            let sample = match sample {
+                Ok(sample) => sample,
+                Err(e) => {
+                    tracing::debug!("stats stream of server {} ended: {e}", self.uuid);
+                    break;
+                }
+            };
+
+            {
+                let mut stats = self.stats.lock().await;
+
+                stats.memory_bytes = memory_usage(&sample) as usize;
+                stats.memory_limit_bytes = sample.memory_stats.limit.unwrap_or(0) as usize;
+                stats.cpu_absolute = absolute_cpu(&sample.precpu_stats, &sample.cpu_stats);
+                stats.network = network_usage(&sample);
+                stats.uptime = started_at.map_or(0, uptime_millis);
+            }
+
+            self.broadcast_stats().await;
+        }
+
+        // Nothing is running anymore. The disk usage is kept separately and
+        // stays meaningful.
+        *self.stats.lock().await = PerformanceStatisics::default();
+
+        self.broadcast_stats().await;
+    }
+
+    /// Starts recalculating the size of the server volume every
+    /// [`DISK_USAGE_INTERVAL`], whether or not the container runs, so offline
+    /// servers report their disk usage too. The task only holds a weak
+    /// reference and ends once the server is dropped.
+    pub(super) async fn spawn_disk_usage_sampler(self: &Arc<Self>) {
+        let handle = tokio::spawn(sample_disk_usage(Arc::downgrade(self)));
+
+        if let Some(previous) = self.disk_usage_sampler.lock().await.replace(handle) {
+            previous.abort();
+        }
+    }
+
+    async fn container_started_at(&self) -> Option<OffsetDateTime> {
+        let response = self
+            .docker
+            .inspect_container(&self.container_name, None)
+            .await
+            .ok()?;
+
+        let started_at = response.state?.started_at?;
+
+        OffsetDateTime::parse(&started_at, &Rfc3339).ok()
+    }
+}
+
+async fn sample_disk_usage(server: Weak<Server>) {
+    let mut interval = tokio::time::interval(DISK_USAGE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let Some(server) = server.upgrade() else {
+            break;
+        };
+
+        let disk_bytes = directory_size(&server.volume_dir).await;
+        server.disk_bytes.store(disk_bytes, Ordering::Relaxed);
+    }
+}
+
+/// Memory used by the container, not counting the page cache the kernel can
+/// reclaim at will. This is what `docker stats` displays.
+fn memory_usage(sample: &Stats) -> u64 {
+    let usage = sample.memory_stats.usage.unwrap_or(0);
+
+    let inactive_file = match sample.memory_stats.stats {
+        Some(MemoryStatsStats::V1(stats)) => stats.total_inactive_file,
+        Some(MemoryStatsStats::V2(stats)) => stats.inactive_file,
+        None => 0,
+    };
+
+    if inactive_file < usage {
+        usage - inactive_file
+    } else {
+        usage
+    }
+}
+
+/// CPU usage since the previous sample, where 100% is one full core.
+fn absolute_cpu(previous: &CPUStats, current: &CPUStats) -> f64 {
+    let cpu_delta = current.cpu_usage.total_usage as f64 - previous.cpu_usage.total_usage as f64;
+    let system_delta = current.system_cpu_usage.unwrap_or(0) as f64
+        - previous.system_cpu_usage.unwrap_or(0) as f64;
+
+    let cpus = match current.online_cpus {
+        Some(cpus) if cpus > 0 => cpus as f64,
+        _ => current
+            .cpu_usage
+            .percpu_usage
+            .as_ref()
+            .map_or(0.0, |usage| usage.len() as f64),
+    };
+
+    if cpu_delta <= 0.0 || system_delta <= 0.0 {
+        return 0.0;
+    }
+
+    let mut percent = cpu_delta / system_delta * 100.0;
+
+    if cpus > 0.0 {
+        percent *= cpus;
+    }
+
+    (percent * 1000.0).round() / 1000.0
+}
+
+fn network_usage(sample: &Stats) -> NetworkStatistics {
+    let mut network = NetworkStatistics::default();
+
+    for stats in sample.networks.iter().flat_map(|n| n.values()) {
+        network.rx_bytes += stats.rx_bytes as usize;
+        network.tx_bytes += stats.tx_bytes as usize;
+    }
+
+    network
+}
+
+fn uptime_millis(started_at: OffsetDateTime) -> u64 {
+    let elapsed = OffsetDateTime::now_utc() - started_at;
+    elapsed.whole_milliseconds().max(0) as u64
+}
+
+/// Total size of the files under `root`. Symbolic links are not followed and
+/// entries that cannot be read are skipped.
+async fn directory_size(root: &Path) -> u64 {
+    let mut total = 0;
+    let mut pending = vec![root.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let Ok(mut entries) = tokio::fs::read_dir(&dir).await else {
+            continue;
+        };
+
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let Ok(metadata) = tokio::fs::symlink_metadata(entry.path()).await else {
+                continue;
+            };
+
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else {
+                total += metadata.len();
+            }
+        }
+    }
+
+    total
+}
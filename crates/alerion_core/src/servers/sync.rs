@@ -0,0 +1,167 @@
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use alerion_datamodel::remote::server::{BuildConfig, ServerSettings};
+use alerion_datamodel::websocket::{PowerAction, ServerStatus};
+use bollard::container::UpdateContainerOptions;
+use bollard::errors::Error as DockerError;
+
+use super::{container, Server, ServerError, ServerInfo};
+
+impl Server {
+    /// Fetches the server's configuration from the panel again and applies
+    /// it. Resource limits are updated on the existing container right away,
+    /// while changes baked into the container (image, ports, mounts,
+    /// environment) only take effect once it is rebuilt on the next start.
+    #[tracing::instrument(skip(self))]
+    pub async fn sync(self: &Arc<Self>) -> Result<(), ServerError> {
+        let uuid = self.uuid.as_hyphenated();
+        let config = self.remote_api.get_server_configuration(self.uuid).await?;
+
+        let info = Arc::new(ServerInfo::from_remote_info(
+            config.settings,
+            config.process_configuration,
+        ));
+
+        let previous = self.server_info.send_replace(Arc::clone(&info));
+        let (old, new) = (&previous.settings, &info.settings);
+
+        if requires_rebuild(old, new) {
+            tracing::info!("server {uuid} will be rebuilt on its next start");
+            self.rebuild_required.store(true, Ordering::SeqCst);
+        }
+
+        if old.build != new.build {
+            if let Err(e) = self.update_resources(&new.build).await {
+                tracing::warn!(
+                    "failed to update resource limits of server {uuid}, \
+                     rebuilding it on its next start instead: {e}"
+                );
+
+                self.rebuild_required.store(true, Ordering::SeqCst);
+            }
+        }
+
+        if new.suspended && !old.suspended && self.status() != ServerStatus::Offline {
+            tracing::info!("server {uuid} was suspended, stopping it");
+
+            if let Err(e) = self.power(PowerAction::Stop) {
+                tracing::warn!("failed to stop suspended server {uuid}: {e}");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies `build` to the existing container. There is nothing to do if
+    /// the container does not exist yet, since it will be created with the
+    /// new limits.
+    async fn update_resources(&self, build: &BuildConfig) -> Result<(), ServerError> {
+        let resources = container::resources(build);
+
+        let opts = UpdateContainerOptions::<String> {
+            memory: resources.memory,
+            memory_reservation: resources.memory_reservation,
+            memory_swap: resources.memory_swap,
+            blkio_weight: resources.blkio_weight,
+            oom_kill_disable: resources.oom_kill_disable,
+            cpu_quota: resources.cpu_quota,
+            cpu_period: resources.cpu_period,
+            cpu_shares: resources.cpu_shares.map(|shares| shares as isize),
+            cpuset_cpus: resources.cpuset_cpus,
+            ..UpdateContainerOptions::default()
+        };
+
+        match self
+            .docker
+            .update_container(&self.container_name, opts)
+            .await
+        {
+            Ok(())
+            | Err(DockerError::DockerResponseServerError {
+                status_code: 404, ..
+            }) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Whether going from `old` to `new` changes anything Docker cannot update on
+/// an existing container.
+fn requires_rebuild(old: &ServerSettings, new: &ServerSettings) -> bool {
+    // Docker can change limits in place, but not lift them.
+    let limits_lifted = (old.build.memory_limit > 0 && new.build.memory_limit == 0)
+        || (old.build.cpu_limit > 0 && new.build.cpu_limit == 0)
+        || (old.build.threads.is_some() && new.build.threads.is_none());
+
+    // The container's environment is built from these, SERVER_MEMORY
+    // included. They are compared directly since the order of the variables
+    // is not stable.
+    let environment_changed = old.environment != new.environment
+        || old.invocation != new.invocation
+        || old.build.memory_limit != new.build.memory_limit;
+
+    new.container.requires_rebuild
+        || old.container.image != new.container.image
+        || old.allocations != new.allocations
+        || old.mounts != new.mounts
+        || environment_changed
+        || limits_lifted
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::servers::test_support;
+
+    /// Enough environment variables for their iteration order to vary.
+    fn settings() -> ServerSettings {
+        let mut settings = test_support::settings();
+        settings.environment = ["A", "B", "C", "D", "E"]
+            .into_iter()
+            .enumerate()
+            .map(|(i, key)| (key.to_owned(), json!(i)))
+            .collect();
+        settings
+    }
+
+    #[test]
+    fn same_settings_need_no_rebuild() {
+        // Separately deserialized maps may iterate in different orders.
+        for _ in 0..16 {
+            assert!(!requires_rebuild(&settings(), &settings()));
+        }
+    }
+
+    #[test]
+    fn environment_changes_need_a_rebuild() {
+        let mut new = settings();
+        new.environment.insert("A".to_owned(), json!("changed"));
+        assert!(requires_rebuild(&settings(), &new));
+
+        let mut new = settings();
+        new.invocation = "java -Xmx1G -jar server.jar".to_owned();
+        assert!(requires_rebuild(&settings(), &new));
+    }
+
+    #[test]
+    fn lifted_limits_need_a_rebuild() {
+        let mut new = settings();
+        new.build.memory_limit = 0;
+        assert!(requires_rebuild(&settings(), &new));
+
+        let mut new = settings();
+        new.build.cpu_limit = 0;
+        assert!(requires_rebuild(&settings(), &new));
+    }
+
+    #[test]
+    fn tightened_limits_are_updated_in_place() {
+        let mut new = settings();
+        new.build.cpu_limit = 50;
+        new.build.io_weight = 100;
+        assert!(!requires_rebuild(&settings(), &new));
+    }
+}
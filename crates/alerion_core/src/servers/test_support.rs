@@ -0,0 +1,60 @@
+use alerion_datamodel::remote::server::ServerSettings;
+use serde_json::json;
+
+use crate::config::AlerionConfig;
+
+/// Settings of a typical Minecraft server, for tests to adjust the fields
+/// they care about.
+pub fn settings() -> ServerSettings {
+    serde_json::from_value(json!({
+        "uuid": "9ad4f6c1-5d6c-4b2a-8f0e-6f4a4d1c2b3a",
+        "meta": { "name": "lobby", "description": "" },
+        "suspended": false,
+        "environment": { "MOTD": "{{config.auth.token}}", "MAX_PLAYERS": 20 },
+        "invocation": "java -jar server.jar",
+        "skip_egg_scripts": false,
+        "build": {
+            "memory_limit": 1024,
+            "swap": 0,
+            "io_weight": 500,
+            "cpu_limit": 100,
+            "threads": null,
+            "disk_space": 2048,
+            "oom_disabled": false
+        },
+        "container": {
+            "image": "ghcr.io/pterodactyl/yolks:java_17",
+            "oom_disabled": false,
+            "requires_rebuild": false
+        },
+        "allocations": {
+            "force_outgoing_ip": false,
+            "default": { "ip": "0.0.0.0", "port": 25565 },
+            "mappings": { "0.0.0.0": [25565] }
+        },
+        "mounts": [],
+        "egg": { "id": "7a3b2c1d-0e9f-4a8b-9c7d-6e5f4a3b2c1d", "file_denylist": [] }
+    }))
+    .expect("valid server settings")
+}
+
+/// A node configuration with every optional section left at its default, and
+/// an extra API token so tests can check credentials do not leak.
+pub fn config() -> AlerionConfig {
+    serde_json::from_value(json!({
+        "debug": false,
+        "uuid": "node",
+        "api": {
+            "host": "0.0.0.0",
+            "port": 8080,
+            "ssl": { "enabled": false, "cert": "", "key": "" }
+        },
+        "auth": {
+            "token": "secret",
+            "token_id": "id",
+            "extra_tokens": [{ "token": "other-secret", "token_id": "other" }]
+        },
+        "remote": "https://panel.example.com"
+    }))
+    .expect("valid config")
+}
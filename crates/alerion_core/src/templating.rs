@@ -0,0 +1,230 @@
+use std::convert::Infallible;
+
+use alerion_datamodel::remote::server::ServerSettings;
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::config::AlerionConfig;
+
+#[derive(Debug, Error)]
+pub enum TemplateError {
+    #[error("unknown placeholder {{{{{0}}}}}")]
+    UnknownKey(String),
+}
+
+/// Resolves the `{{...}}` placeholders the panel puts in egg configuration
+/// file values, the startup command and environment values.
+///
+/// Server users can edit their startup command and variables, so the
+/// `config.*` namespace only exposes node settings that are safe for them to
+/// read, never credentials.
+///
+/// Three namespaces are understood:
+/// - `server.*`, the server's build and allocation settings (for example
+///   `server.build.default.port` or `server.build.memory`),
+/// - `env.*`, the server's environment variables,
+/// - `config.*`, a fixed set of node settings, named as in the Wings
+///   configuration (for example `config.docker.interface`).
+pub struct TemplateContext<'a> {
+    settings: &'a ServerSettings,
+    config: &'a AlerionConfig,
+}
+
+impl<'a> TemplateContext<'a> {
+    pub fn new(settings: &'a ServerSettings, config: &'a AlerionConfig) -> Self {
+        Self { settings, config }
+    }
+
+    /// Replaces every placeholder in `template`. An opening `{{` without a
+    /// matching `}}` is not a placeholder and is kept as is.
+    pub fn render(&self, template: &str) -> Result<String, TemplateError> {
+        substitute(template, |key| self.lookup(key).map(Some))
+    }
+
+    /// Renders the startup command or an environment value. Placeholders
+    /// without a namespace, such as `{{SERVER_JARFILE}}`, are left for the
+    /// image's entrypoint to expand, as is anything that cannot be resolved,
+    /// so a bad value never keeps the container from being created.
+    pub fn render_startup(&self, template: &str) -> String {
+        substitute(template, |key| Ok::<_, Infallible>(self.lookup(key).ok()))
+            .unwrap_or_else(|never| match never {})
+    }
+
+    fn lookup(&self, key: &str) -> Result<String, TemplateError> {
+        let unknown = || TemplateError::UnknownKey(key.to_owned());
+
+        if let Some(name) = key.strip_prefix("env.") {
+            self.lookup_env(name).ok_or_else(unknown)
+        } else if let Some(path) = key.strip_prefix("config.") {
+            self.lookup_config(path).ok_or_else(unknown)
+        } else if let Some(path) = key.strip_prefix("server.") {
+            self.lookup_server(path).ok_or_else(unknown)
+        } else {
+            Err(unknown())
+        }
+    }
+
+    /// Looks up an environment variable, including the ones the daemon sets
+    /// for every server.
+    fn lookup_env(&self, name: &str) -> Option<String> {
+        let settings = self.settings;
+
+        if let Some(value) = settings.environment.get(name) {
+            return Some(value_to_string(value));
+        }
+
+        match name {
+            "STARTUP" => Some(settings.invocation.clone()),
+            "SERVER_MEMORY" => Some(settings.build.memory_limit.to_string()),
+            "SERVER_IP" => Some(settings.allocations.default.ip.clone()),
+            "SERVER_PORT" => Some(settings.allocations.default.port.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Looks up one of the node settings eggs may refer to. Anything not
+    /// listed here, credentials in particular, is never exposed.
+    fn lookup_config(&self, path: &str) -> Option<String> {
+        let config = self.config;
+
+        let value = match path {
+            "docker.interface" | "docker.network.interface" => config.docker.interface.clone(),
+            "api.host" => config.api.host.to_string(),
+            "api.port" => config.api.port.to_string(),
+            "api.ssl.enabled" => config.api.ssl.enabled.to_string(),
+            "uuid" => config.uuid.clone(),
+            "remote" => config.remote.clone(),
+            "debug" => config.debug.to_string(),
+            _ => return None,
+        };
+
+        Some(value)
+    }
+
+    fn lookup_server(&self, path: &str) -> Option<String> {
+        let settings = self.settings;
+        let build = &settings.build;
+
+        if let Some(name) = path.strip_prefix("build.env.") {
+            return self.lookup_env(name);
+        }
+
+        let value = match path {
+            "uuid" => settings.uuid.as_hyphenated().to_string(),
+            "name" => settings.meta.name.clone(),
+            "build.default.ip" => settings.allocations.default.ip.clone(),
+            "build.default.port" => settings.allocations.default.port.to_string(),
+            "build.memory" => build.memory_limit.to_string(),
+            "build.swap" => build.swap.to_string(),
+            "build.io" => build.io_weight.to_string(),
+            "build.cpu" => build.cpu_limit.to_string(),
+            "build.disk" => build.disk_space.to_string(),
+            "build.threads" => build.threads.clone().unwrap_or_default(),
+            "build.oom_disabled" => build.oom_disabled.to_string(),
+            _ => return None,
+        };
+
+        Some(value)
+    }
+}
+
+/// Replaces every `{{key}}` in `template` with what `lookup` returns for it,
+/// keeping placeholders it returns `None` for as they were written.
+fn substitute<E>(
+    template: &str,
+    lookup: impl Fn(&str) -> Result<Option<String>, E>,
+) -> Result<String, E> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+
+        let Some(end) = after.find("}}") else {
+            break;
+        };
+
+        out.push_str(&rest[..start]);
+
+        match lookup(after[..end].trim())? {
+            Some(value) => out.push_str(&value),
+            None => out.push_str(&rest[start..start + 2 + end + 2]),
+        }
+
+        rest = &after[end + 2..];
+    }
+
+    out.push_str(rest);
+
+    Ok(out)
+}
+
+/// Formats a JSON value the way it should appear inside a larger string:
+/// strings without quotes, `null` as nothing.
+pub fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::servers::test_support::{config, settings};
+
+    #[test]
+    fn resolves_every_namespace() {
+        let (settings, config) = (settings(), config());
+        let template = TemplateContext::new(&settings, &config);
+
+        let rendered = template
+            .render(
+                "{{server.build.default.port}} {{env.MAX_PLAYERS}} {{ config.docker.interface }}",
+            )
+            .expect("known placeholders");
+
+        assert_eq!(rendered, "25565 20 172.18.0.1");
+    }
+
+    #[test]
+    fn never_exposes_credentials() {
+        let (settings, config) = (settings(), config());
+        let template = TemplateContext::new(&settings, &config);
+
+        for key in [
+            "{{config.auth.token}}",
+            "{{config.auth.token_id}}",
+            "{{config.auth.extra_tokens}}",
+            "{{config.auth}}",
+        ] {
+            assert!(template.render(key).is_err(), "{key} should not resolve");
+        }
+    }
+
+    #[test]
+    fn values_are_not_rendered_again() {
+        let (settings, config) = (settings(), config());
+        let template = TemplateContext::new(&settings, &config);
+
+        assert_eq!(
+            template.render("{{env.MOTD}}").expect("known placeholder"),
+            "{{config.auth.token}}"
+        );
+    }
+
+    #[test]
+    fn unterminated_placeholders_pass_through() {
+        let (settings, config) = (settings(), config());
+        let template = TemplateContext::new(&settings, &config);
+
+        assert_eq!(template.render("a {{ b").expect("no placeholder"), "a {{ b");
+        assert_eq!(
+            template
+                .render("{{server.name}} {{ c")
+                .expect("known placeholder"),
+            "lobby {{ c"
+        );
+    }
+}
@@ -1,21 +1,36 @@
 use std::env::consts::{ARCH, OS};
 use std::io;
 use std::sync::Arc;
+use std::time::Duration;
 
-use alerion_datamodel::webserver::CreateServerRequest;
-use poem::listener::TcpListener;
-use poem::middleware::{Tracing, Cors};
+use alerion_datamodel::webserver::{
+    CreateServerRequest, DenyTokensRequest, SendCommandsRequest, ServerLogsQuery, ServerLogsResponse, ServerPowerRequest
+};
+use alerion_datamodel::websocket::ServerStatus;
+use poem::http::header;
+use poem::listener::{Listener, TcpListener};
+use poem::middleware::{Cors, Tracing};
 use poem::web::websocket::WebSocket;
-use poem::web::{Data, Json, Path};
-use poem::{endpoint, get, handler, post, Body, EndpointExt, IntoResponse, Route, Server};
+use poem::web::{Data, Json, Path, Query};
+use poem::{endpoint, get, handler, post, EndpointExt, IntoResponse, Request, Route, Server};
 use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 use sysinfo::System;
 use uuid::Uuid;
 
+use self::cors::AllowedOrigins;
 use self::middleware::bearer_auth::BearerAuthMiddleware;
+use self::middleware::private_network::PrivateNetworkMiddleware;
+use self::websocket::auth::Auth;
 use crate::config::AlerionConfig;
-use crate::servers::ServerPool;
+use crate::servers::console::strip_ansi;
+use crate::servers::{ServerError, ServerPool};
+
+/// Longest a power request may wait for its action to complete.
+const MAX_POWER_WAIT_SECONDS: u64 = 300;
+
+/// What a power request waits for when it asks for more than the maximum.
+const DEFAULT_POWER_WAIT_SECONDS: u64 = 30;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct SystemResponseV1 {
@@ -46,12 +61,24 @@ async fn get_system_info() -> impl IntoResponse {
 async fn initialize_websocket(
     Path(uuid): Path<Uuid>,
     Data(server_pool): Data<&Arc<ServerPool>>,
+    Data(auth): Data<&Arc<Auth>>,
+    Data(origins): Data<&Arc<AllowedOrigins>>,
+    req: &Request,
     ws: WebSocket,
 ) -> impl IntoResponse {
+    let origin = req
+        .headers()
+        .get(header::ORIGIN)
+        .and_then(|value| value.to_str().ok());
+
+    if !origin.is_some_and(|origin| origins.allows(origin)) {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
     if let Some(server) = server_pool.get_server(uuid).await {
-        let recv = server.add_websocket_connection().await;
+        let auth = Arc::clone(auth);
 
-        ws.on_upgrade(move |mut socket| websocket::websocket_handler(socket, recv, uuid))
+        ws.on_upgrade(move |socket| websocket::websocket_handler(socket, server, auth))
             .into_response()
     } else {
         StatusCode::NOT_FOUND.into_response()
@@ -59,11 +86,36 @@ async fn initialize_websocket(
 }
 
 #[handler]
-async fn create_server(Json(options): Json<CreateServerRequest>, Data(server_pool): Data<&Arc<ServerPool>>) -> impl IntoResponse {
+async fn list_servers(Data(server_pool): Data<&Arc<ServerPool>>) -> impl IntoResponse {
+    let mut servers = Vec::new();
+
+    for server in server_pool.servers().await {
+        servers.push(server.details().await);
+    }
+
+    Json(servers)
+}
+
+#[handler]
+async fn get_server(
+    Path(uuid): Path<Uuid>,
+    Data(server_pool): Data<&Arc<ServerPool>>,
+) -> impl IntoResponse {
+    match server_pool.get_server(uuid).await {
+        Some(server) => Json(server.details().await).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+#[handler]
+async fn create_server(
+    Json(options): Json<CreateServerRequest>,
+    Data(server_pool): Data<&Arc<ServerPool>>,
+) -> impl IntoResponse {
     let server = match server_pool.get_server(options.uuid).await {
         Some(s) => s,
         None => {
-            let server_fut = server_pool.register_server(options.uuid, options.start_on_completion);
+            let server_fut = server_pool.register_server(options.uuid);
             let Ok(server) = server_fut.await else {
                 return StatusCode::INTERNAL_SERVER_ERROR.into_response();
             };
@@ -72,38 +124,264 @@ async fn create_server(Json(options): Json<CreateServerRequest>, Data(server_poo
         }
     };
 
-    ().into_response()
+    match server.install(false, options.start_on_completion).await {
+        Ok(()) => StatusCode::ACCEPTED.into_response(),
+        Err(
+            ServerError::InstallInProgress
+            | ServerError::PowerActionInProgress
+            | ServerError::Deleting,
+        ) => StatusCode::CONFLICT.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[handler]
+async fn delete_server(
+    Path(uuid): Path<Uuid>,
+    Data(server_pool): Data<&Arc<ServerPool>>,
+) -> impl IntoResponse {
+    match server_pool.remove_server(uuid).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => {
+            tracing::error!("failed to delete server {uuid}: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// How long a power request waits for its action to complete. Same bounds as
+/// Wings: anything out of range waits the default instead.
+fn power_wait(wait_seconds: Option<i64>) -> Duration {
+    let seconds = match wait_seconds {
+        Some(seconds) => u64::try_from(seconds)
+            .ok()
+            .filter(|seconds| *seconds <= MAX_POWER_WAIT_SECONDS)
+            .unwrap_or(DEFAULT_POWER_WAIT_SECONDS),
+        None => 0,
+    };
+
+    Duration::from_secs(seconds)
+}
+
+#[handler]
+async fn post_server_power(
+    Path(uuid): Path<Uuid>,
+    Json(request): Json<ServerPowerRequest>,
+    Data(server_pool): Data<&Arc<ServerPool>>,
+) -> impl IntoResponse {
+    let Some(server) = server_pool.get_server(uuid).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let handle = match server.power(request.action) {
+        Ok(handle) => handle,
+        Err(ServerError::PowerActionInProgress | ServerError::Deleting) => {
+            return StatusCode::CONFLICT.into_response()
+        }
+        Err(ServerError::Suspended) => return StatusCode::FORBIDDEN.into_response(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+
+    let wait = power_wait(request.wait_seconds);
+
+    if wait.is_zero() {
+        return StatusCode::ACCEPTED.into_response();
+    }
+
+    // The action keeps running in the background if the wait runs out.
+    match tokio::time::timeout(wait, handle).await {
+        Ok(Ok(Err(ServerError::AlreadyRunning))) => StatusCode::CONFLICT.into_response(),
+        Ok(Ok(Err(e))) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        Ok(Err(_)) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        Ok(Ok(Ok(()))) | Err(_) => StatusCode::ACCEPTED.into_response(),
+    }
+}
+
+#[handler]
+async fn post_server_commands(
+    Path(uuid): Path<Uuid>,
+    Json(request): Json<SendCommandsRequest>,
+    Data(server_pool): Data<&Arc<ServerPool>>,
+) -> impl IntoResponse {
+    let Some(server) = server_pool.get_server(uuid).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    if server.status() == ServerStatus::Offline {
+        return StatusCode::BAD_GATEWAY.into_response();
+    }
+
+    for command in &request.commands {
+        match server.send_command(command).await {
+            Ok(()) => {}
+            Err(ServerError::NotRunning) => return StatusCode::BAD_GATEWAY.into_response(),
+            Err(e) => {
+                tracing::warn!("failed to send command to server {uuid}: {e}");
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        }
+    }
+
+    StatusCode::NO_CONTENT.into_response()
+}
+
+#[handler]
+async fn get_server_logs(
+    Path(uuid): Path<Uuid>,
+    Query(query): Query<ServerLogsQuery>,
+    Data(server_pool): Data<&Arc<ServerPool>>,
+    Data(config): Data<&Arc<AlerionConfig>>,
+) -> impl IntoResponse {
+    let Some(server) = server_pool.get_server(uuid).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let size = query
+        .size
+        .filter(|size| *size > 0)
+        .map_or(config.max_log_lines, |size| size.min(config.max_log_lines));
+
+    let mut lines = match server.logs(size).await {
+        Ok(lines) => lines,
+        Err(e) => {
+            tracing::warn!("failed to read logs of server {uuid}: {e}");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if query.strip_ansi {
+        for line in &mut lines {
+            *line = strip_ansi(line).into_owned();
+        }
+    }
+
+    Json(ServerLogsResponse { data: lines }).into_response()
+}
+
+#[handler]
+async fn post_server_sync(
+    Path(uuid): Path<Uuid>,
+    Data(server_pool): Data<&Arc<ServerPool>>,
+) -> impl IntoResponse {
+    let Some(server) = server_pool.get_server(uuid).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    match server.sync().await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e @ ServerError::RemoteApi(_)) => {
+            tracing::warn!("failed to fetch configuration of server {uuid}: {e}");
+            StatusCode::BAD_GATEWAY.into_response()
+        }
+        Err(e) => {
+            tracing::error!("failed to sync server {uuid}: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[handler]
+async fn deny_websocket_tokens(
+    Path(uuid): Path<Uuid>,
+    Json(request): Json<DenyTokensRequest>,
+    Data(server_pool): Data<&Arc<ServerPool>>,
+) -> impl IntoResponse {
+    let Some(server) = server_pool.get_server(uuid).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    server.deny_tokens(request.jtis).await;
+
+    StatusCode::NO_CONTENT.into_response()
 }
 
 pub async fn serve(config: &AlerionConfig, server_pool: Arc<ServerPool>) -> io::Result<()> {
-    // TODO: restrict origins
-    let cors = Cors::new().allow_credentials(true);
+    let origins = Arc::new(AllowedOrigins::from_config(config));
+
+    let cors_origins = Arc::clone(&origins);
+    let cors = Cors::new()
+        .allow_credentials(true)
+        .allow_origins_fn(move |origin| cors_origins.allows(origin));
 
     let system_endpoint = get(get_system_info)
         .options(endpoint::make_sync(|_| StatusCode::NO_CONTENT))
-        .with(BearerAuthMiddleware::new(config.auth.token.clone()));
+        .with(BearerAuthMiddleware::new(&config.auth));
 
     let ws_endpoint = get(initialize_websocket);
 
-    let install_endpoint = post(create_server)
-        .with(BearerAuthMiddleware::new(config.auth.token.clone()));
+    let servers_endpoint = get(list_servers)
+        .post(create_server)
+        .with(BearerAuthMiddleware::new(&config.auth));
+
+    let server_endpoint = get(get_server)
+        .delete(delete_server)
+        .with(BearerAuthMiddleware::new(&config.auth));
+
+    let power_endpoint = post(post_server_power).with(BearerAuthMiddleware::new(&config.auth));
+
+    let commands_endpoint =
+        post(post_server_commands).with(BearerAuthMiddleware::new(&config.auth));
+
+    let logs_endpoint = get(get_server_logs).with(BearerAuthMiddleware::new(&config.auth));
+
+    let sync_endpoint = post(post_server_sync).with(BearerAuthMiddleware::new(&config.auth));
+
+    let deny_endpoint = post(deny_websocket_tokens).with(BearerAuthMiddleware::new(&config.auth));
 
     let api = Route::new()
         .nest(
             "api",
             Route::new()
                 .at("system", system_endpoint)
-                .at("servers", install_endpoint)
-                .at("servers/:uuid/ws", ws_endpoint),
+                .at("servers", servers_endpoint)
+                .at("servers/:uuid", server_endpoint)
+                .at("servers/:uuid/power", power_endpoint)
+                .at("servers/:uuid/commands", commands_endpoint)
+                .at("servers/:uuid/logs", logs_endpoint)
+                .at("servers/:uuid/sync", sync_endpoint)
+                .at("servers/:uuid/ws", ws_endpoint)
+                .at("servers/:uuid/ws/deny", deny_endpoint),
         )
         .with(cors)
-        .with(Tracing::default())
-        .data(server_pool);
+        .with_if(
+            config.api.allow_cors_private_network,
+            PrivateNetworkMiddleware,
+        )
+        .with(Tracing)
+        .data(server_pool)
+        .data(origins)
+        .data(Arc::new(Auth::from_config(config)))
+        .data(Arc::new(config.clone()));
+
+    let listener = TcpListener::bind((config.api.host, config.api.port));
+
+    if config.api.ssl.enabled {
+        let tls = tls::config_stream(&config.api.ssl).await?;
+
+        Server::new(listener.rustls(tls)).run(api).await
+    } else {
+        Server::new(listener).run(api).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    Server::new(TcpListener::bind((config.api.host, config.api.port)))
-        .run(api)
-        .await
+    #[test]
+    fn power_wait_bounds() {
+        assert_eq!(power_wait(None), Duration::ZERO);
+        assert_eq!(power_wait(Some(0)), Duration::ZERO);
+        assert_eq!(power_wait(Some(300)), Duration::from_secs(300));
+
+        let default = Duration::from_secs(DEFAULT_POWER_WAIT_SECONDS);
+        assert_eq!(power_wait(Some(-1)), default);
+        assert_eq!(power_wait(Some(301)), default);
+    }
 }
 
+pub mod cors;
 pub mod middleware;
+pub mod tls;
 pub mod websocket;
+
@@ -0,0 +1,85 @@
+use reqwest::Url;
+
+use crate::config::AlerionConfig;
+
+/// Browser origins allowed to call the API and open websockets: the panel
+/// itself plus any extra origin from the configuration, where `*` allows
+/// every origin.
+#[derive(Debug, Clone)]
+pub struct AllowedOrigins {
+    origins: Vec<String>,
+    any: bool,
+}
+
+impl AllowedOrigins {
+    pub fn from_config(config: &AlerionConfig) -> Self {
+        let extra = &config.api.allowed_origins;
+
+        let origins = std::iter::once(&config.remote)
+            .chain(extra.iter().filter(|o| o.as_str() != "*"))
+            .map(|origin| normalize(origin))
+            .collect();
+
+        Self {
+            origins,
+            any: extra.iter().any(|o| o == "*"),
+        }
+    }
+
+    pub fn allows(&self, origin: &str) -> bool {
+        self.any
+            || self
+                .origins
+                .iter()
+                .any(|allowed| *allowed == normalize(origin))
+    }
+}
+
+/// Reduces a URL to its `scheme://host[:port]` origin, which is what browsers
+/// send in the `Origin` header.
+fn normalize(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(url) => url.origin().ascii_serialization(),
+        Err(_) => url.trim_end_matches('/').to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::servers::test_support::config;
+
+    fn origins(allowed_origins: &[&str]) -> AllowedOrigins {
+        let mut config = config();
+        config.remote = "https://panel.example.com/".to_owned();
+        config.api.allowed_origins = allowed_origins.iter().map(|o| (*o).to_owned()).collect();
+
+        AllowedOrigins::from_config(&config)
+    }
+
+    #[test]
+    fn allows_the_panel_regardless_of_trailing_slash() {
+        let origins = origins(&[]);
+
+        assert!(origins.allows("https://panel.example.com"));
+        assert!(origins.allows("https://panel.example.com/"));
+        assert!(!origins.allows("http://panel.example.com"));
+        assert!(!origins.allows("https://evil.example.com"));
+    }
+
+    #[test]
+    fn default_ports_are_ignored() {
+        let origins = origins(&["http://localhost:80", "https://dash.example.com:8443"]);
+
+        assert!(origins.allows("http://localhost"));
+        assert!(origins.allows("https://panel.example.com:443"));
+        assert!(origins.allows("https://dash.example.com:8443"));
+        assert!(!origins.allows("https://dash.example.com"));
+    }
+
+    #[test]
+    fn wildcard_allows_every_origin() {
+        assert!(origins(&["*"]).allows("https://anything.example.com"));
+        assert!(!origins(&[]).allows("https://anything.example.com"));
+    }
+}
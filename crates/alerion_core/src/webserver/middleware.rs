@@ -1 +1,2 @@
 pub mod bearer_auth;
+pub mod private_network;
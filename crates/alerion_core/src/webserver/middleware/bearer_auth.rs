@@ -1,13 +1,86 @@
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
 use poem::{Endpoint, Middleware, Request};
 use reqwest::{Method, StatusCode};
 
-pub struct BearerAuthMiddleware {
+use crate::config::AlerionAuthentication;
+
+/// A node credential the API accepts.
+struct AcceptedToken {
+    token_id: String,
     token: String,
+    expires_at: Option<SystemTime>,
+}
+
+impl AcceptedToken {
+    /// Checks `credential`, either the bare token or `<token_id>.<token>`,
+    /// in constant time with respect to the token contents.
+    fn matches(&self, credential: &str, now: SystemTime) -> bool {
+        if self.expires_at.is_some_and(|expires_at| now >= expires_at) {
+            return false;
+        }
+
+        let bare = constant_time_eq(credential.as_bytes(), self.token.as_bytes());
+
+        let with_id = match credential.split_once('.') {
+            Some((token_id, token)) => {
+                constant_time_eq(token_id.as_bytes(), self.token_id.as_bytes())
+                    & constant_time_eq(token.as_bytes(), self.token.as_bytes())
+            }
+            None => false,
+        };
+
+        bare | with_id
+    }
+}
+
+/// Checks the value of an `Authorization` header against every accepted
+/// token. Every token is checked so timing does not reveal which one, if any,
+/// matched.
+fn accepts(tokens: &[AcceptedToken], header: &str, now: SystemTime) -> bool {
+    let Some(credential) = header.strip_prefix("Bearer ") else {
+        return false;
+    };
+
+    tokens.iter().fold(false, |accepted, token| {
+        accepted | token.matches(credential, now)
+    })
+}
+
+/// Compares two byte strings without stopping at the first difference, so
+/// the time taken does not reveal how much of a guess was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+pub struct BearerAuthMiddleware {
+    tokens: Arc<Vec<AcceptedToken>>,
 }
 
 impl BearerAuthMiddleware {
-    pub fn new(token: String) -> Self {
-        Self { token }
+    pub fn new(auth: &AlerionAuthentication) -> Self {
+        let main = AcceptedToken {
+            token_id: auth.token_id.clone(),
+            token: auth.token.clone(),
+            expires_at: None,
+        };
+
+        let extra = auth.extra_tokens.iter().map(|extra| AcceptedToken {
+            token_id: extra.token_id.clone(),
+            token: extra.token.clone(),
+            expires_at: extra
+                .expires_at
+                .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs)),
+        });
+
+        Self {
+            tokens: Arc::new(std::iter::once(main).chain(extra).collect()),
+        }
     }
 }
 
@@ -17,7 +90,7 @@ impl<E: Endpoint> Middleware<E> for BearerAuthMiddleware {
     fn transform(&self, ep: E) -> Self::Output {
         BearerAuthMiddlewareImpl {
             ep,
-            token: self.token.clone(),
+            tokens: Arc::clone(&self.tokens),
         }
     }
 }
@@ -25,7 +98,7 @@ impl<E: Endpoint> Middleware<E> for BearerAuthMiddleware {
 /// The new endpoint type generated by the TokenMiddleware.
 pub struct BearerAuthMiddlewareImpl<E> {
     ep: E,
-    token: String,
+    tokens: Arc<Vec<AcceptedToken>>,
 }
 
 /// Token data
@@ -42,9 +115,7 @@ impl<E: Endpoint> Endpoint for BearerAuthMiddlewareImpl<E> {
             .get("Authorization")
             .and_then(|value| value.to_str().ok())
         {
-            let token = value.to_string();
-
-            if token == format!("Bearer {}", self.token) {
+            if accepts(&self.tokens, value, SystemTime::now()) {
                 self.ep.call(req).await
             } else {
                 Err(poem::Error::from_string(
@@ -60,3 +131,64 @@ impl<E: Endpoint> Endpoint for BearerAuthMiddlewareImpl<E> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(token_id: &str, token: &str, expires_at: Option<SystemTime>) -> AcceptedToken {
+        AcceptedToken {
+            token_id: token_id.to_owned(),
+            token: token.to_owned(),
+            expires_at,
+        }
+    }
+
+    #[test]
+    fn accepts_bare_and_prefixed_tokens() {
+        let tokens = [token("main", "secret", None)];
+        let now = SystemTime::now();
+
+        assert!(accepts(&tokens, "Bearer secret", now));
+        assert!(accepts(&tokens, "Bearer main.secret", now));
+        assert!(!accepts(&tokens, "Bearer other.secret", now));
+        assert!(!accepts(&tokens, "Bearer main.wrong", now));
+    }
+
+    #[test]
+    fn requires_the_bearer_prefix() {
+        let tokens = [token("main", "secret", None)];
+        let now = SystemTime::now();
+
+        assert!(!accepts(&tokens, "secret", now));
+        assert!(!accepts(&tokens, "Basic secret", now));
+        assert!(!accepts(&tokens, "bearer secret", now));
+    }
+
+    #[test]
+    fn accepts_extra_tokens_until_they_expire() {
+        let now = SystemTime::now();
+        let tokens = [
+            token("main", "secret", None),
+            token("extra", "fresh", Some(now + Duration::from_secs(60))),
+            token("old", "stale", Some(now)),
+        ];
+
+        assert!(accepts(&tokens, "Bearer fresh", now));
+        assert!(accepts(&tokens, "Bearer extra.fresh", now));
+        assert!(!accepts(&tokens, "Bearer stale", now));
+        assert!(!accepts(&tokens, "Bearer old.stale", now));
+    }
+
+    #[test]
+    fn different_lengths_never_match() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secre"));
+        assert!(!constant_time_eq(b"secret", b"secrets"));
+        assert!(!constant_time_eq(b"", b"secret"));
+
+        let tokens = [token("main", "secret", None)];
+        assert!(!accepts(&tokens, "Bearer secret ", SystemTime::now()));
+        assert!(!accepts(&tokens, "Bearer ", SystemTime::now()));
+    }
+}
@@ -0,0 +1,44 @@
+use poem::http::HeaderValue;
+use poem::{Endpoint, IntoResponse, Middleware, Request, Response};
+use reqwest::Method;
+
+const REQUEST_PRIVATE_NETWORK: &str = "Access-Control-Request-Private-Network";
+const ALLOW_PRIVATE_NETWORK: &str = "Access-Control-Allow-Private-Network";
+
+/// Answers Private Network Access preflights, which browsers send before a
+/// public page (the panel) may reach a daemon on a private address.
+pub struct PrivateNetworkMiddleware;
+
+impl<E: Endpoint> Middleware<E> for PrivateNetworkMiddleware {
+    type Output = PrivateNetworkMiddlewareImpl<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        PrivateNetworkMiddlewareImpl { ep }
+    }
+}
+
+pub struct PrivateNetworkMiddlewareImpl<E> {
+    ep: E,
+}
+
+impl<E: Endpoint> Endpoint for PrivateNetworkMiddlewareImpl<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> poem::Result<Self::Output> {
+        let is_preflight = req.method() == Method::OPTIONS
+            && req
+                .headers()
+                .get(REQUEST_PRIVATE_NETWORK)
+                .is_some_and(|value| value == "true");
+
+        let mut response = self.ep.call(req).await?.into_response();
+
+        if is_preflight {
+            response
+                .headers_mut()
+                .insert(ALLOW_PRIVATE_NETWORK, HeaderValue::from_static("true"));
+        }
+
+        Ok(response)
+    }
+}
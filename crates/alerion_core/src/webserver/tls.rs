@@ -0,0 +1,80 @@
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use futures::stream::{self, Stream, StreamExt};
+use poem::listener::{RustlsCertificate, RustlsConfig};
+
+use crate::config::AlerionApiSsl;
+
+/// How often the certificate files are checked for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Certificate and key file paths, along with their modification times when
+/// they were last loaded.
+struct CertificateFiles {
+    cert: PathBuf,
+    key: PathBuf,
+    loaded: (SystemTime, SystemTime),
+}
+
+impl CertificateFiles {
+    async fn modified(&self) -> io::Result<(SystemTime, SystemTime)> {
+        let cert = tokio::fs::metadata(&self.cert).await?.modified()?;
+        let key = tokio::fs::metadata(&self.key).await?.modified()?;
+
+        Ok((cert, key))
+    }
+
+    async fn load(&self) -> io::Result<RustlsConfig> {
+        let cert = tokio::fs::read(&self.cert).await?;
+        let key = tokio::fs::read(&self.key).await?;
+
+        Ok(RustlsConfig::new().fallback(RustlsCertificate::new().cert(cert).key(key)))
+    }
+}
+
+/// Loads the configured certificate, then yields it again every time the
+/// certificate or key file changes on disk, so renewed certificates are
+/// picked up without restarting the daemon.
+pub async fn config_stream(
+    ssl: &AlerionApiSsl,
+) -> io::Result<impl Stream<Item = RustlsConfig> + Send + Unpin + 'static> {
+    let mut files = CertificateFiles {
+        cert: PathBuf::from(&ssl.cert),
+        key: PathBuf::from(&ssl.key),
+        loaded: (SystemTime::UNIX_EPOCH, SystemTime::UNIX_EPOCH),
+    };
+
+    files.loaded = files.modified().await?;
+    let initial = files.load().await?;
+
+    let reloads = stream::unfold(files, |mut files| async move {
+        loop {
+            tokio::time::sleep(RELOAD_INTERVAL).await;
+
+            let modified = match files.modified().await {
+                Ok(modified) => modified,
+                Err(e) => {
+                    tracing::warn!("failed to check TLS certificate files: {e}");
+                    continue;
+                }
+            };
+
+            if modified == files.loaded {
+                continue;
+            }
+
+            match files.load().await {
+                Ok(config) => {
+                    tracing::info!("reloading TLS certificate {}", files.cert.display());
+                    files.loaded = modified;
+                    return Some((config, files));
+                }
+                Err(e) => tracing::warn!("failed to reload TLS certificate: {e}"),
+            }
+        }
+    });
+
+    Ok(stream::once(async { initial }).chain(reloads).boxed())
+}
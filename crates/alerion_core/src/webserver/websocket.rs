@@ -1,143 +1,402 @@
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
+use alerion_datamodel::websocket::{PowerAction, ServerStatus, WebsocketEvent};
 use futures::stream::{SplitSink, StreamExt};
-use poem::web::websocket::{Message, WebSocketStream};
-use serde::{Deserialize, Serialize};
-use tokio::sync::{mpsc, Mutex};
-use uuid::Uuid;
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct RecvWebsocketEvent {
-    event: RecvEventType,
-    args: Option<Vec<String>>,
-}
+use futures::SinkExt;
+use poem::web::websocket::{CloseCode, Message, WebSocketStream};
+use tokio::sync::{Mutex, RwLock};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct SendWebsocketEvent {
-    event: SendEventType,
-    args: Option<Vec<String>>,
-}
+use self::auth::{Auth, Permissions, ValidatedToken};
+use crate::servers::Server;
 
-#[derive(Debug, Serialize, Deserialize)]
-struct AuthDetails {
-    data: AuthDetailsInner,
-}
+/// How long before its token expires a session is sent `token expiring`.
+const TOKEN_EXPIRY_WARNING: Duration = Duration::from_secs(60);
 
-#[derive(Debug, Serialize, Deserialize)]
-struct AuthDetailsInner {
-    token: String,
-    socket: String,
-}
+/// How often clients are pinged and idle connections are looked for.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long a client may go without sending anything, pongs included, before
+/// the connection is considered dead.
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long a connection may stay open without a valid token.
+const AUTH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Why the daemon is closing a connection, sent along in the close frame.
+type CloseReason = (CloseCode, &'static str);
 
-#[derive(Debug, Serialize, Deserialize)]
-pub enum RecvEventType {
-    #[serde(rename = "auth")]
-    Auth,
-    #[serde(rename = "set state")]
-    SetState,
-    #[serde(rename = "send command")]
-    SendCommand,
-    #[serde(rename = "send logs")]
-    SendLogs,
-    #[serde(rename = "send stats")]
-    SendStats,
+/// Close reason used when one of the connection's tasks panicked.
+const INTERNAL_ERROR: CloseReason = (CloseCode::Error, "internal error");
+
+/// State shared by the inbound and outbound halves of a single websocket
+/// connection.
+struct Session {
+    server: Arc<Server>,
+    auth: Arc<Auth>,
+    sink: Mutex<SplitSink<WebSocketStream, Message>>,
+    permissions: RwLock<Permissions>,
+    /// ID and issue time of the token the session authenticated with.
+    token: Mutex<Option<(String, SystemTime)>>,
+    expiry: Mutex<Option<JoinHandle<()>>>,
+    /// When the client last sent a frame of any kind.
+    last_seen: Mutex<Instant>,
+    /// Since when the session has been without a valid token, if it is.
+    unauthenticated_since: Mutex<Option<Instant>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub enum SendEventType {
-    #[serde(rename = "auth success")]
-    AuthSuccess,
-    #[serde(rename = "backup complete")]
-    BackupComplete,
-    #[serde(rename = "backup restore completed")]
-    BackupRestoreCompleted,
-    #[serde(rename = "console output")]
-    ConsoleOutput,
-    #[serde(rename = "daemon error")]
-    DaemonError,
-    #[serde(rename = "daemon message")]
-    DaemonMessage,
-    #[serde(rename = "install completed")]
-    InstallCompleted,
-    #[serde(rename = "install output")]
-    InstallOutput,
-    #[serde(rename = "install started")]
-    InstallStarted,
-    #[serde(rename = "jwt error")]
-    JwtError,
-    #[serde(rename = "stats")]
-    Stats,
-    #[serde(rename = "status")]
-    Status,
-    #[serde(rename = "token expired")]
-    TokenExpired,
-    #[serde(rename = "token expiring")]
-    TokenExpiring,
-    #[serde(rename = "transfer logs")]
-    TransferLogs,
-    #[serde(rename = "transfer status")]
-    TransferStatus,
+impl Session {
+    async fn send(&self, event: WebsocketEvent) {
+        let json = serde_json::to_string(&event).expect("JSON serialization should not fail");
+
+        if let Err(e) = self.sink.lock().await.send(Message::Text(json)).await {
+            tracing::debug!("failed to send websocket message: {e}");
+        }
+    }
+
+    async fn permissions(&self) -> Permissions {
+        *self.permissions.read().await
+    }
+
+    async fn authenticate(self: &Arc<Self>, token: ValidatedToken) {
+        *self.permissions.write().await = token.permissions;
+        *self.token.lock().await = Some((token.jti, token.issued_at));
+        *self.unauthenticated_since.lock().await = None;
+        self.schedule_expiry(token.expires_at).await;
+    }
+
+    /// Drops the session's permissions. The client then has
+    /// [`AUTH_TIMEOUT`] to authenticate again.
+    async fn deauthenticate(&self) {
+        *self.permissions.write().await = Permissions::empty();
+        *self.token.lock().await = None;
+        self.unauthenticated_since
+            .lock()
+            .await
+            .get_or_insert_with(Instant::now);
+    }
+
+    /// Warns the client shortly before its token expires, then revokes the
+    /// session's permissions once it has. Authenticating again replaces the
+    /// schedule of the previous token.
+    async fn schedule_expiry(self: &Arc<Self>, expires_at: SystemTime) {
+        let remaining = expires_at
+            .duration_since(SystemTime::now())
+            .unwrap_or_default();
+
+        let expires = Instant::now() + remaining;
+        let session = Arc::clone(self);
+
+        let handle = tokio::spawn(async move {
+            if let Some(warning) = remaining.checked_sub(TOKEN_EXPIRY_WARNING) {
+                tokio::time::sleep(warning).await;
+                session.send(WebsocketEvent::TokenExpiring).await;
+            }
+
+            tokio::time::sleep_until(expires).await;
+
+            session.deauthenticate().await;
+            session.send(WebsocketEvent::TokenExpired).await;
+        });
+
+        if let Some(previous) = self.expiry.lock().await.replace(handle) {
+            previous.abort();
+        }
+    }
+
+    /// Whether the token the session authenticated with has since been
+    /// denied.
+    async fn is_token_denied(&self) -> bool {
+        let token = self.token.lock().await.clone();
+
+        match token {
+            Some((jti, issued_at)) => self.server.is_token_denied(&jti, issued_at).await,
+            None => false,
+        }
+    }
+
+    async fn cancel_expiry(&self) {
+        if let Some(handle) = self.expiry.lock().await.take() {
+            handle.abort();
+        }
+    }
 }
 
-pub async fn websocket_handler(
-    stream: WebSocketStream,
-    _recv: mpsc::Receiver<SendWebsocketEvent>,
-    uuid: Uuid,
-) {
+pub async fn websocket_handler(stream: WebSocketStream, server: Arc<Server>, auth: Arc<Auth>) {
+    let (id, mut recv) = server.add_websocket_connection().await;
     let (sink, mut stream) = stream.split();
-    let sink = Arc::new(Mutex::new(sink));
 
-    let direct_responder = Arc::clone(&sink);
-    let inbound_handle = tokio::spawn(async move {
+    let session = Arc::new(Session {
+        server: Arc::clone(&server),
+        auth,
+        sink: Mutex::new(sink),
+        permissions: RwLock::new(Permissions::empty()),
+        token: Mutex::new(None),
+        expiry: Mutex::new(None),
+        last_seen: Mutex::new(Instant::now()),
+        unauthenticated_since: Mutex::new(Some(Instant::now())),
+    });
+
+    let inbound_session = Arc::clone(&session);
+    let mut inbound_handle = tokio::spawn(async move {
         while let Some(result) = stream.next().await {
-            if let Ok(msg) = result {
-                match msg {
-                    Message::Text(text) => {
-                        let data = serde_json::from_str::<RecvWebsocketEvent>(text.as_str());
-
-                        match data {
-                            Ok(json) => handle_incoming_message(json, &direct_responder, uuid),
-                            Err(_e) => todo!(),
-                        }
-                    }
+            let msg = match result {
+                Ok(msg) => msg,
+                Err(e) => {
+                    tracing::debug!("error reading from websocket: {e}");
+                    return None;
+                }
+            };
 
-                    Message::Close(_maybe_reason) => {
-                        return;
-                    }
+            *inbound_session.last_seen.lock().await = Instant::now();
 
-                    _ => {
-                        // TODO
-                        return;
+            match msg {
+                Message::Text(text) => match serde_json::from_str::<WebsocketEvent>(&text) {
+                    Ok(event) => handle_incoming_message(event, &inbound_session).await,
+                    Err(e) => {
+                        inbound_session
+                            .send(WebsocketEvent::DaemonError(format!(
+                                "malformed websocket frame: {e}"
+                            )))
+                            .await;
                     }
+                },
+
+                Message::Binary(_) => {
+                    inbound_session
+                        .send(WebsocketEvent::DaemonError(
+                            "binary websocket frames are not supported".to_owned(),
+                        ))
+                        .await;
                 }
+
+                // Pongs to pings are answered by the websocket implementation.
+                Message::Ping(_) | Message::Pong(_) => {}
+
+                Message::Close(_) => return None,
             }
         }
+
+        None
     });
 
-    let outbound_handle = tokio::spawn(async move {
+    let outbound_session = Arc::clone(&session);
+    let mut outbound_handle = tokio::spawn(async move {
+        while let Some(event) = recv.recv().await {
+            let required = required_to_receive(&event);
+
+            if outbound_session.permissions().await.contains(required) {
+                outbound_session.send(event).await;
+            }
+        }
+
+        Some((CloseCode::Away, "server removed"))
+    });
+
+    let denial_session = Arc::clone(&session);
+    let mut denials = server.subscribe_token_denials();
+    let mut denial_handle = tokio::spawn(async move {
+        while denials.changed().await.is_ok() {
+            if denial_session.is_token_denied().await {
+                denial_session
+                    .send(WebsocketEvent::JwtError(
+                        "jwt: token has been revoked".to_owned(),
+                    ))
+                    .await;
+
+                return Some((CloseCode::Policy, "token revoked"));
+            }
+        }
+
+        None
+    });
+
+    let heartbeat_session = Arc::clone(&session);
+    let mut heartbeat_handle = tokio::spawn(async move {
+        let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+
         loop {
-            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-            //sink.send()
+            interval.tick().await;
+
+            if heartbeat_session.last_seen.lock().await.elapsed() >= HEARTBEAT_TIMEOUT {
+                return Some((CloseCode::Away, "heartbeat timeout"));
+            }
+
+            let unauthenticated_since = *heartbeat_session.unauthenticated_since.lock().await;
+
+            if unauthenticated_since.is_some_and(|since| since.elapsed() >= AUTH_TIMEOUT) {
+                return Some((CloseCode::Policy, "authentication timeout"));
+            }
+
+            let ping = heartbeat_session
+                .sink
+                .lock()
+                .await
+                .send(Message::Ping(Vec::new()))
+                .await;
+
+            if let Err(e) = ping {
+                tracing::debug!("failed to ping websocket: {e}");
+                return None;
+            }
         }
     });
 
-    tokio::select! {
-        _ = inbound_handle => {},
-        _ = outbound_handle => {}
+    let reason: Option<CloseReason> = tokio::select! {
+        r = &mut inbound_handle => r.unwrap_or(Some(INTERNAL_ERROR)),
+        r = &mut outbound_handle => r.unwrap_or(Some(INTERNAL_ERROR)),
+        r = &mut denial_handle => r.unwrap_or(Some(INTERNAL_ERROR)),
+        r = &mut heartbeat_handle => r.unwrap_or(Some(INTERNAL_ERROR)),
+    };
+
+    inbound_handle.abort();
+    outbound_handle.abort();
+    denial_handle.abort();
+    heartbeat_handle.abort();
+
+    {
+        let mut sink = session.sink.lock().await;
+
+        if let Some((code, reason)) = reason {
+            tracing::debug!("closing websocket: {reason}");
+
+            if let Err(e) = sink.send(Message::close_with(code, reason)).await {
+                tracing::debug!("failed to send websocket close frame: {e}");
+            }
+        }
+
+        if let Err(e) = sink.close().await {
+            tracing::debug!("failed to close websocket: {e}");
+        }
     }
+
+    session.cancel_expiry().await;
+    server.remove_websocket_connection(id).await;
 }
 
-fn handle_incoming_message(
-    msg: RecvWebsocketEvent,
-    _sink: &Mutex<SplitSink<WebSocketStream, Message>>,
-    _uuid: Uuid,
-) {
-    match msg.event {
-        RecvEventType::Auth => {
-            // handle auth..
+/// Returns the permissions a session must hold to be sent `event`.
+fn required_to_receive(event: &WebsocketEvent) -> Permissions {
+    match event {
+        WebsocketEvent::ConsoleOutput(_) => Permissions::CONNECT | Permissions::CONSOLE,
+        WebsocketEvent::InstallOutput(_) => Permissions::CONNECT | Permissions::ADMIN_INSTALL,
+        WebsocketEvent::BackupCompleted(_) | WebsocketEvent::BackupRestoreCompleted => {
+            Permissions::CONNECT | Permissions::BACKUP_READ
+        }
+        WebsocketEvent::TransferLogs(_) | WebsocketEvent::TransferStatus(_) => {
+            Permissions::CONNECT | Permissions::ADMIN_TRANSFER
+        }
+        _ => Permissions::CONNECT,
+    }
+}
+
+/// Returns the permission a session must hold to have `event` processed, or
+/// `None` if clients are not supposed to send it.
+fn required_permission(event: &WebsocketEvent) -> Option<Permissions> {
+    match event {
+        WebsocketEvent::Auth(_) => Some(Permissions::empty()),
+        WebsocketEvent::SendStats => Some(Permissions::CONNECT),
+        WebsocketEvent::SendCommand(_) | WebsocketEvent::SendLogs => Some(Permissions::CONSOLE),
+        WebsocketEvent::SetState(action) => match action {
+            PowerAction::Start => Some(Permissions::START),
+            PowerAction::Stop | PowerAction::Kill => Some(Permissions::STOP),
+            PowerAction::Restart => Some(Permissions::RESTART),
+        },
+        _ => None,
+    }
+}
+
+async fn handle_incoming_message(event: WebsocketEvent, session: &Arc<Session>) {
+    if let WebsocketEvent::Auth(token) = &event {
+        let token = session
+            .auth
+            .validate(token, &session.server.uuid())
+            .filter(|t| t.permissions.contains(Permissions::CONNECT));
+
+        let token = match token {
+            Some(t) if session.server.is_token_denied(&t.jti, t.issued_at).await => None,
+            token => token,
+        };
+
+        match token {
+            Some(token) => {
+                session.authenticate(token).await;
+                session.send(WebsocketEvent::AuthSuccess).await;
+
+                let status = session.server.status();
+                session.send(WebsocketEvent::Status(status)).await;
+            }
+
+            None => {
+                session.cancel_expiry().await;
+                session.deauthenticate().await;
+                session
+                    .send(WebsocketEvent::JwtError("jwt: invalid token".to_owned()))
+                    .await;
+            }
+        }
+
+        return;
+    }
+
+    let permissions = session.permissions().await;
+
+    if !permissions.contains(Permissions::CONNECT) {
+        session
+            .send(WebsocketEvent::JwtError(
+                "jwt: not authenticated".to_owned(),
+            ))
+            .await;
+
+        return;
+    }
+
+    match required_permission(&event) {
+        Some(required) if permissions.contains(required) => {}
+        Some(_) => {
+            tracing::debug!("refusing websocket event {event:?}: missing permission");
+            return;
+        }
+        None => {
+            tracing::debug!("ignoring websocket event {event:?}: not a client event");
+            return;
+        }
+    }
+
+    match event {
+        WebsocketEvent::SetState(action) => {
+            if let Err(e) = session.server.power(action) {
+                session
+                    .send(WebsocketEvent::DaemonError(e.to_string()))
+                    .await;
+            }
+        }
+
+        WebsocketEvent::SendCommand(command) => {
+            if session.server.status() == ServerStatus::Offline {
+                return;
+            }
+
+            if let Err(e) = session.server.send_command(&command).await {
+                tracing::debug!("failed to send command from websocket: {e}");
+            }
+        }
+
+        WebsocketEvent::SendLogs => {
+            for line in session.server.console_history().await {
+                session.send(WebsocketEvent::ConsoleOutput(line)).await;
+            }
+        }
+
+        WebsocketEvent::SendStats => {
+            let stats = session.server.stats().await;
+            session.send(WebsocketEvent::Stats(stats)).await;
         }
 
-        _ => todo!(),
+        // Authentication is handled before permissions are checked, and
+        // anything else was filtered out by `required_permission`.
+        _ => {}
     }
 }
 
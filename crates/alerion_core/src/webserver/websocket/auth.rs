@@ -1,4 +1,5 @@
 use std::collections::HashSet;
+use std::time::{Duration, SystemTime};
 
 use bitflags::bitflags;
 use jsonwebtoken::{Algorithm, DecodingKey, Validation};
@@ -86,6 +87,15 @@ impl Permissions {
     }
 }
 
+/// What a successfully validated websocket token grants.
+#[derive(Debug, Clone)]
+pub struct ValidatedToken {
+    pub permissions: Permissions,
+    pub jti: String,
+    pub issued_at: SystemTime,
+    pub expires_at: SystemTime,
+}
+
 pub struct Auth {
     validation: Validation,
     key: DecodingKey,
@@ -100,8 +110,8 @@ impl Auth {
         validation.required_spec_claims = HashSet::from(spec_claims);
         validation.leeway = 10;
         validation.reject_tokens_expiring_in_less_than = 0;
-        validation.validate_exp = false;
-        validation.validate_nbf = false;
+        validation.validate_exp = true;
+        validation.validate_nbf = true;
         validation.validate_aud = false;
         validation.aud = None;
         validation.iss = Some(HashSet::from([cfg.remote.clone()]));
@@ -112,10 +122,15 @@ impl Auth {
         Self { validation, key }
     }
 
-    pub fn validate(&self, auth: &str, server_uuid: &Uuid) -> Option<Permissions> {
+    pub fn validate(&self, auth: &str, server_uuid: &Uuid) -> Option<ValidatedToken> {
         jsonwebtoken::decode::<Claims>(auth, &self.key, &self.validation)
             .ok()
             .filter(|result| &result.claims.server_uuid == server_uuid)
-            .map(|result| Permissions::from_strings(&result.claims.permissions))
+            .map(|result| ValidatedToken {
+                permissions: Permissions::from_strings(&result.claims.permissions),
+                issued_at: SystemTime::UNIX_EPOCH + Duration::from_secs(result.claims.iat as u64),
+                jti: result.claims.jti,
+                expires_at: SystemTime::UNIX_EPOCH + Duration::from_secs(result.claims.exp as u64),
+            })
     }
 }
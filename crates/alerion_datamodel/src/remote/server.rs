@@ -10,7 +10,14 @@ use uuid::Uuid;
 pub struct SearchReplaceMatcher {
     #[serde(rename = "match")]
     pub match_item: String,
-    pub replace_with: String,
+    /// Only replace values currently equal to this, or matching the regex
+    /// after a `regex:` prefix.
+    #[serde(default)]
+    pub if_value: Option<String>,
+    /// Usually a string or another scalar. Eggs can also give an object
+    /// mapping conditions, as in `if_value`, to the value to use when each
+    /// one holds.
+    pub replace_with: Value,
 }
 
 #[derive(Debug, Deserialize)]
@@ -24,13 +31,17 @@ pub struct FileParser {
 pub enum StopSignalType {
     #[serde(rename = "command")]
     Command,
+    #[serde(rename = "signal")]
+    Signal,
+    #[serde(rename = "stop")]
+    Stop,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct StopConfig {
     #[serde(rename = "type")]
     pub kind: StopSignalType,
-    pub value: String,
+    pub value: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -47,41 +58,41 @@ pub struct ProcessConfig {
     pub configs: SmallVec<[FileParser; 1]>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Egg {
     pub id: Uuid,
     // todo: figure out what is inside this array
     pub file_denylist: Vec<Value>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Mount {
     pub source: String,
     pub target: String,
     pub read_only: bool,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Allocation {
     pub ip: String,
     pub port: u16,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct AllocationConfig {
     pub force_outgoing_ip: bool,
     pub default: Allocation,
     pub mappings: HashMap<String, SmallVec<[u16; 2]>>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContainerConfig {
     pub image: String,
     pub oom_disabled: bool,
     pub requires_rebuild: bool,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct BuildConfig {
     pub memory_limit: isize,
     pub swap: isize,
@@ -92,13 +103,13 @@ pub struct BuildConfig {
     pub oom_disabled: bool,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerMetadata {
     pub name: String,
     pub description: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerSettings {
     pub uuid: Uuid,
     pub meta: ServerMetadata,
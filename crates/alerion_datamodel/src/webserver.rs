@@ -1,6 +1,9 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::remote::server::ServerSettings;
+use crate::websocket::{PerformanceStatisics, PowerAction, ServerStatus};
+
 #[derive(Serialize, Deserialize)]
 pub struct SystemOptions {
     pub architecture: &'static str,
@@ -16,4 +19,53 @@ pub struct CreateServerRequest {
     pub start_on_completion: bool,
 }
 
+/// Request to `POST /api/servers/{uuid}/power`
+#[derive(Serialize, Deserialize)]
+pub struct ServerPowerRequest {
+    pub action: PowerAction,
+    /// How long to wait for the action to complete before responding, up to
+    /// 300 seconds. The response is sent right away when absent or zero.
+    /// Signed so that a negative value gets the default wait instead of
+    /// failing the request.
+    #[serde(default)]
+    pub wait_seconds: Option<i64>,
+}
+
+/// Request to `POST /api/servers/{uuid}/commands`
+#[derive(Serialize, Deserialize)]
+pub struct SendCommandsRequest {
+    pub commands: Vec<String>,
+}
+
+/// Query of `GET /api/servers/{uuid}/logs`
+#[derive(Serialize, Deserialize)]
+pub struct ServerLogsQuery {
+    /// How many of the latest lines to return.
+    pub size: Option<usize>,
+    /// Whether ANSI escape sequences are removed from the lines.
+    #[serde(default)]
+    pub strip_ansi: bool,
+}
+
+/// Response to `GET /api/servers/{uuid}/logs`
+#[derive(Serialize, Deserialize)]
+pub struct ServerLogsResponse {
+    pub data: Vec<String>,
+}
+
+/// Request to `POST /api/servers/{uuid}/ws/deny`
+#[derive(Serialize, Deserialize)]
+pub struct DenyTokensRequest {
+    pub jtis: Vec<String>,
+}
+
+/// A server as returned by `GET /api/servers` and `GET /api/servers/{uuid}`
+#[derive(Serialize)]
+pub struct ServerDetails {
+    pub state: ServerStatus,
+    pub is_suspended: bool,
+    pub utilization: PerformanceStatisics,
+    pub configuration: ServerSettings,
+}
+
 pub mod update;
@@ -1,8 +1,9 @@
-use bytestring::ByteString;
 use serde::{Deserialize, Serialize};
-use smallvec::{smallvec, SmallVec};
+use serde_json::Value;
+use thiserror::Error;
+use uuid::Uuid;
 
-#[derive(Copy, Clone, Debug, Serialize, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
 pub enum ServerStatus {
     #[serde(rename = "running")]
     Running,
@@ -11,16 +12,71 @@ pub enum ServerStatus {
     #[serde(rename = "stopping")]
     Stopping,
     #[serde(rename = "offline")]
+    #[default]
     Offline,
 }
 
-#[derive(Debug, Clone, Serialize)]
+impl ServerStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ServerStatus::Running => "running",
+            ServerStatus::Starting => "starting",
+            ServerStatus::Stopping => "stopping",
+            ServerStatus::Offline => "offline",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "running" => Some(ServerStatus::Running),
+            "starting" => Some(ServerStatus::Starting),
+            "stopping" => Some(ServerStatus::Stopping),
+            "offline" => Some(ServerStatus::Offline),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum PowerAction {
+    #[serde(rename = "start")]
+    Start,
+    #[serde(rename = "stop")]
+    Stop,
+    #[serde(rename = "restart")]
+    Restart,
+    #[serde(rename = "kill")]
+    Kill,
+}
+
+impl PowerAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PowerAction::Start => "start",
+            PowerAction::Stop => "stop",
+            PowerAction::Restart => "restart",
+            PowerAction::Kill => "kill",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "start" => Some(PowerAction::Start),
+            "stop" => Some(PowerAction::Stop),
+            "restart" => Some(PowerAction::Restart),
+            "kill" => Some(PowerAction::Kill),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct NetworkStatistics {
     pub rx_bytes: usize,
     pub tx_bytes: usize,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct PerformanceStatisics {
     pub memory_bytes: usize,
     pub memory_limit_bytes: usize,
@@ -31,80 +87,251 @@ pub struct PerformanceStatisics {
     pub disk_bytes: usize,
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
-pub enum EventType {
-    #[serde(rename = "auth")]
-    Authentication,
-    #[serde(rename = "auth success")]
-    AuthenticationSuccess,
-    #[serde(rename = "stats")]
-    Stats,
-    #[serde(rename = "logs")]
-    Logs,
-    #[serde(rename = "console output")]
-    ConsoleOutput,
-    #[serde(rename = "install output")]
-    InstallOutput,
-    #[serde(rename = "install completed")]
-    InstallCompleted,
-    #[serde(rename = "status")]
-    Status,
-    #[serde(rename = "send logs")]
+/// Payload of a `backup completed` event.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackupCompletion {
+    pub uuid: Uuid,
+    pub is_successful: bool,
+    pub checksum: String,
+    pub checksum_type: String,
+    pub file_size: u64,
+}
+
+/// Every event exchanged over a server websocket, in either direction.
+///
+/// On the wire, events are `{"event": "...", "args": ["..."]}` frames whose
+/// arguments are always strings; structured payloads such as stats are JSON
+/// encoded into their single argument.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawMessage", into = "RawMessage")]
+pub enum WebsocketEvent {
+    // Sent by clients.
+    Auth(String),
+    SetState(PowerAction),
+    SendCommand(String),
     SendLogs,
-    #[serde(rename = "send stats")]
     SendStats,
-    #[serde(rename = "send command")]
-    SendCommand,
-    #[serde(rename = "set state")]
-    SetState,
-    #[serde(rename = "daemon error")]
-    DaemonError,
-    #[serde(rename = "jwt error")]
-    JwtError,
+
+    // Sent by the daemon.
+    AuthSuccess,
+    TokenExpiring,
+    TokenExpired,
+    JwtError(String),
+    Status(ServerStatus),
+    Stats(PerformanceStatisics),
+    ConsoleOutput(String),
+    DaemonMessage(String),
+    DaemonError(String),
+    InstallStarted,
+    InstallOutput(String),
+    InstallCompleted,
+    BackupCompleted(BackupCompletion),
+    BackupRestoreCompleted,
+    TransferLogs(String),
+    TransferStatus(String),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct RawMessage {
-    event: EventType,
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    args: Option<SmallVec<[serde_json::Value; 1]>>,
+#[derive(Debug, Error)]
+pub enum ProtocolError {
+    #[error("unknown event {0:?}")]
+    UnknownEvent(String),
+    #[error("event {0:?} is missing its argument")]
+    MissingArgument(String),
+    #[error("event {0:?} has an invalid argument")]
+    InvalidArgument(String),
+    #[error("invalid JSON payload: {0}")]
+    Json(#[from] serde_json::Error),
 }
 
-impl From<RawMessage> for ByteString {
-    fn from(value: RawMessage) -> Self {
-        serde_json::to_string(&value)
-            .expect("infallible struct-to-json conversion")
-            .into()
-    }
+/// A frame as it appears on the wire.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RawMessage {
+    event: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    args: Vec<Value>,
 }
 
 impl RawMessage {
-    pub fn new_no_args(event: EventType) -> Self {
-        Self { event, args: None }
+    fn new(event: impl Into<String>, arg: Option<String>) -> Self {
+        Self {
+            event: event.into(),
+            args: arg.into_iter().map(Value::String).collect(),
+        }
     }
 
-    pub fn new(event: EventType, args: String) -> Self {
-        Self {
-            event,
-            args: Some(smallvec![serde_json::Value::String(args)]),
+    fn arg(&self) -> Result<&str, ProtocolError> {
+        self.args
+            .first()
+            .and_then(Value::as_str)
+            .ok_or_else(|| ProtocolError::MissingArgument(self.event.clone()))
+    }
+
+    fn arg_with<T>(&self, parse: impl FnOnce(&str) -> Option<T>) -> Result<T, ProtocolError> {
+        parse(self.arg()?).ok_or_else(|| ProtocolError::InvalidArgument(self.event.clone()))
+    }
+}
+
+impl From<WebsocketEvent> for RawMessage {
+    fn from(event: WebsocketEvent) -> Self {
+        use WebsocketEvent as E;
+
+        match event {
+            E::Auth(token) => RawMessage::new("auth", Some(token)),
+            E::SetState(action) => RawMessage::new("set state", Some(action.as_str().to_owned())),
+            E::SendCommand(command) => RawMessage::new("send command", Some(command)),
+            E::SendLogs => RawMessage::new("send logs", None),
+            E::SendStats => RawMessage::new("send stats", None),
+            E::AuthSuccess => RawMessage::new("auth success", None),
+            E::TokenExpiring => RawMessage::new("token expiring", None),
+            E::TokenExpired => RawMessage::new("token expired", None),
+            E::JwtError(message) => RawMessage::new("jwt error", Some(message)),
+            E::Status(status) => RawMessage::new("status", Some(status.as_str().to_owned())),
+            E::Stats(stats) => RawMessage::new("stats", Some(to_json(&stats))),
+            E::ConsoleOutput(line) => RawMessage::new("console output", Some(line)),
+            E::DaemonMessage(message) => RawMessage::new("daemon message", Some(message)),
+            E::DaemonError(message) => RawMessage::new("daemon error", Some(message)),
+            E::InstallStarted => RawMessage::new("install started", None),
+            E::InstallOutput(line) => RawMessage::new("install output", Some(line)),
+            E::InstallCompleted => RawMessage::new("install completed", None),
+            // The panel listens for the completion of each backup separately.
+            E::BackupCompleted(backup) => RawMessage::new(
+                format!("backup completed:{}", backup.uuid.as_hyphenated()),
+                Some(to_json(&backup)),
+            ),
+            E::BackupRestoreCompleted => RawMessage::new("backup restore completed", None),
+            E::TransferLogs(line) => RawMessage::new("transfer logs", Some(line)),
+            E::TransferStatus(status) => RawMessage::new("transfer status", Some(status)),
         }
     }
+}
 
-    pub fn into_first_arg(self) -> Option<String> {
-        let mut args = self.args?;
-        let json_str = args.get_mut(0)?.take();
+impl TryFrom<RawMessage> for WebsocketEvent {
+    type Error = ProtocolError;
 
-        match json_str {
-            serde_json::Value::String(s) => Some(s),
-            _ => None,
+    fn try_from(raw: RawMessage) -> Result<Self, Self::Error> {
+        use WebsocketEvent as E;
+
+        let owned_arg = |raw: &RawMessage| raw.arg().map(ToOwned::to_owned);
+
+        let event = match raw.event.as_str() {
+            "auth" => E::Auth(owned_arg(&raw)?),
+            "set state" => E::SetState(raw.arg_with(PowerAction::from_name)?),
+            "send command" => E::SendCommand(owned_arg(&raw)?),
+            "send logs" => E::SendLogs,
+            "send stats" => E::SendStats,
+            "auth success" => E::AuthSuccess,
+            "token expiring" => E::TokenExpiring,
+            "token expired" => E::TokenExpired,
+            "jwt error" => E::JwtError(owned_arg(&raw)?),
+            "status" => E::Status(raw.arg_with(ServerStatus::from_name)?),
+            "stats" => E::Stats(serde_json::from_str(raw.arg()?)?),
+            "console output" => E::ConsoleOutput(owned_arg(&raw)?),
+            "daemon message" => E::DaemonMessage(owned_arg(&raw)?),
+            "daemon error" => E::DaemonError(owned_arg(&raw)?),
+            "install started" => E::InstallStarted,
+            "install output" => E::InstallOutput(owned_arg(&raw)?),
+            "install completed" => E::InstallCompleted,
+            "backup restore completed" => E::BackupRestoreCompleted,
+            "transfer logs" => E::TransferLogs(owned_arg(&raw)?),
+            "transfer status" => E::TransferStatus(owned_arg(&raw)?),
+            name if name.starts_with("backup completed:") => {
+                E::BackupCompleted(serde_json::from_str(raw.arg()?)?)
+            }
+            name => return Err(ProtocolError::UnknownEvent(name.to_owned())),
+        };
+
+        Ok(event)
+    }
+}
+
+fn to_json(value: &impl Serialize) -> String {
+    serde_json::to_string(value).expect("infallible struct-to-json conversion")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Frames as sent by Wings and the panel, byte for byte.
+    const WINGS_FRAMES: &[&str] = &[
+        r#"{"event":"auth","args":["eyJ0eXAiOiJKV1QiLCJhbGciOiJIUzI1NiJ9.e30.c2lnbmF0dXJl"]}"#,
+        r#"{"event":"auth success"}"#,
+        r#"{"event":"set state","args":["restart"]}"#,
+        r#"{"event":"send command","args":["say hello world"]}"#,
+        r#"{"event":"status","args":["running"]}"#,
+        r#"{"event":"console output","args":["[12:00:01] [Server thread/INFO]: Done (4.213s)! For help, type \"help\""]}"#,
+        r#"{"event":"stats","args":["{\"memory_bytes\":1073741824,\"memory_limit_bytes\":2147483648,\"cpu_absolute\":12.345,\"network\":{\"rx_bytes\":1024,\"tx_bytes\":2048},\"uptime\":60000,\"state\":\"running\",\"disk_bytes\":524288000}"]}"#,
+        r#"{"event":"daemon message","args":["Checking server disk space usage, this could take a few seconds..."]}"#,
+        r#"{"event":"daemon error","args":["another power action is currently being processed for this server"]}"#,
+        r#"{"event":"jwt error","args":["jwt: exp claim is invalid"]}"#,
+        r#"{"event":"token expiring"}"#,
+        r#"{"event":"token expired"}"#,
+        r#"{"event":"install started"}"#,
+        r#"{"event":"install output","args":["Downloading server jar..."]}"#,
+        r#"{"event":"install completed"}"#,
+        r#"{"event":"backup completed:3f1a4b6e-2c1d-4a8e-9f0b-5d6c7e8f9a0b","args":["{\"uuid\":\"3f1a4b6e-2c1d-4a8e-9f0b-5d6c7e8f9a0b\",\"is_successful\":true,\"checksum\":\"d41d8cd98f00b204e9800998ecf8427e\",\"checksum_type\":\"sha1\",\"file_size\":4096}"]}"#,
+        r#"{"event":"backup restore completed"}"#,
+        r#"{"event":"transfer logs","args":["Archiving server files..."]}"#,
+        r#"{"event":"transfer status","args":["completed"]}"#,
+    ];
+
+    #[test]
+    fn wings_frames_round_trip() {
+        for frame in WINGS_FRAMES {
+            let event: WebsocketEvent = serde_json::from_str(frame)
+                .unwrap_or_else(|e| panic!("failed to parse {frame}: {e}"));
+
+            let serialized = serde_json::to_string(&event).expect("serialization");
+
+            assert_eq!(&serialized, frame);
         }
     }
 
-    pub fn event(&self) -> EventType {
-        self.event
+    #[test]
+    fn parses_typed_payloads() {
+        let stats: WebsocketEvent = serde_json::from_str(WINGS_FRAMES[6]).expect("stats frame");
+
+        let WebsocketEvent::Stats(stats) = stats else {
+            panic!("expected stats, got {stats:?}");
+        };
+
+        assert_eq!(stats.memory_bytes, 1073741824);
+        assert_eq!(stats.network.tx_bytes, 2048);
+        assert_eq!(stats.state, ServerStatus::Running);
+
+        let action: WebsocketEvent = serde_json::from_str(WINGS_FRAMES[2]).expect("power frame");
+        assert_eq!(action, WebsocketEvent::SetState(PowerAction::Restart));
+    }
+
+    #[test]
+    fn accepts_null_arguments_from_the_panel() {
+        // The panel always sends an argument array, even for events that take
+        // none.
+        let event: WebsocketEvent =
+            serde_json::from_str(r#"{"event":"send logs","args":[null]}"#).expect("send logs");
+
+        assert_eq!(event, WebsocketEvent::SendLogs);
+        assert_eq!(
+            serde_json::to_string(&event).expect("serialization"),
+            r#"{"event":"send logs"}"#
+        );
     }
 
-    pub fn into_args(self) -> Option<SmallVec<[serde_json::Value; 1]>> {
-        self.args
+    #[test]
+    fn rejects_malformed_frames() {
+        let frames = [
+            r#"{"event":"set state","args":["explode"]}"#,
+            r#"{"event":"send command"}"#,
+            r#"{"event":"stats","args":["not json"]}"#,
+            r#"{"event":"no such event"}"#,
+            r#"{"args":["missing event"]}"#,
+        ];
+
+        for frame in frames {
+            assert!(
+                serde_json::from_str::<WebsocketEvent>(frame).is_err(),
+                "{frame} should not parse"
+            );
+        }
     }
 }